@@ -0,0 +1,32 @@
+// writes a static executable without nasm or ld, for the target named by the
+// first argument (x86_64, i386 or arm):
+// cargo run --example executable -- i386 && ./hello
+use coreasm::{elf, maker, Arch, Bit, CoreAsm, Target, OS};
+use std::path::Path;
+
+fn main() {
+    let target = match std::env::args().nth(1).as_deref() {
+        Some("i386") => Target::new(Bit::X32, Arch::X86, OS::Linux),
+        Some("arm") => Target::new(Bit::X32, Arch::Arm, OS::Linux),
+        _ => Target::new(Bit::X64, Arch::X86, OS::Linux),
+    };
+    let written = CoreAsm::builder()
+        .i32("answer", 42)
+        .f64("pi", 3.25)
+        .print(|p| {
+            p.text("answer = ")
+                .var("answer")
+                .text(", roughly pi = ")
+                .float("pi", 2)
+                .nl()
+        })
+        .exit(0)
+        .build()
+        .and_then(|asm| maker::executable(&asm, target))
+        .map_err(|e| e.to_string())
+        .and_then(|image| elf::write(Path::new("hello"), &image).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,31 @@
+// prints the assembly for a hello world on x86-64 linux:
+// cargo run --example hello > hello.asm
+use coreasm::{maker, Arch, Bit, CoreAsm, Print, PrintToken, Target, Types, VarValue, OS};
+
+fn main() {
+    // Initialize CoreAsm and add variables
+    let mut asm = CoreAsm::new();
+    // add variables to data section
+    asm.data.mkvar(
+        "name".to_string(),
+        Types::String,
+        VarValue::String("joy".to_string()),
+    );
+    //print
+    let mut print = Print::new();
+    print.add_token(PrintToken::Text("Hello ".to_string()));
+    print.add_token(PrintToken::Variable("name".to_string()));
+    print.add_token(PrintToken::Text("!".to_string()));
+    print.add_token(PrintToken::Newline);
+    asm.prints.push(print);
+    asm.exit(true);
+    //generate assembly code
+    match maker(&asm, Target::new(Bit::X64, Arch::X86, OS::Linux)) {
+        Ok(asmcode) => println!("{}", asmcode),
+        // unsupported targets, undefined variables, type mismatches...
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
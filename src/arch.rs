@@ -1,228 +1,576 @@
-use std::collections::HashMap;
+use crate::error::MakeError;
+use std::{fmt, str::FromStr};
 
-#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Bit {
     X32,
     X64,
 }
 
-#[allow(dead_code)]
-#[derive(Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Arch {
     X86,
     Arm,
 }
 
-#[allow(dead_code)]
-#[derive(Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OS {
     Linux,
     Windows,
     Mac,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Target {
     pub bit: Bit,
     pub arch: Arch,
     pub os: OS,
 }
 
+// written as a short triple, `x86_64-linux`, `i686-linux`, `aarch64-linux`,
+// `armv7-linux`, with `windows` or `macos` for the other systems
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arch = match (self.arch, self.bit) {
+            (Arch::X86, Bit::X64) => "x86_64",
+            (Arch::X86, Bit::X32) => "i686",
+            (Arch::Arm, Bit::X64) => "aarch64",
+            (Arch::Arm, Bit::X32) => "armv7",
+        };
+        let os = match self.os {
+            OS::Linux => "linux",
+            OS::Windows => "windows",
+            OS::Mac => "macos",
+        };
+        write!(f, "{}-{}", arch, os)
+    }
+}
+
+// takes the short triples Display writes, their usual aliases (amd64, i386,
+// arm64, darwin, ...) and full triples with a vendor and environment such as
+// x86_64-unknown-linux-gnu
+impl FromStr for Target {
+    type Err = MakeError;
+
+    fn from_str(triple: &str) -> Result<Self, Self::Err> {
+        let unknown = || MakeError::UnknownTarget(triple.to_string());
+        let mut parts = triple.split('-');
+        let (bit, arch) = match parts.next().ok_or_else(unknown)? {
+            "x86_64" | "amd64" | "x64" => (Bit::X64, Arch::X86),
+            "i386" | "i486" | "i586" | "i686" | "x86" => (Bit::X32, Arch::X86),
+            "aarch64" | "arm64" => (Bit::X64, Arch::Arm),
+            "arm" | "armv7" | "armv7l" | "armv7a" | "armhf" => (Bit::X32, Arch::Arm),
+            _ => return Err(unknown()),
+        };
+        // the system is whichever later part names one; vendors and
+        // environments around it are ignored
+        let os = parts
+            .find_map(|part| match part {
+                "linux" => Some(OS::Linux),
+                "windows" | "win32" => Some(OS::Windows),
+                "macos" | "darwin" | "apple" => Some(OS::Mac),
+                _ => None,
+            })
+            .ok_or_else(unknown)?;
+        Ok(Target { bit, arch, os })
+    }
+}
+
+// operand size, picks the register name (rax/eax/al, x0/w0, d0/s0)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    W8,
+    W32,
+    W64,
+}
+
+// x86 registers in encoding order, sized by Width when printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum X86Reg {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+    Xmm0,
+    Xmm1,
+    Xmm2,
+    Xmm3,
+}
+
+// aarch64 registers, x/w by Width and d/s for the float ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum A64Reg {
+    X0,
+    X1,
+    X2,
+    X3,
+    X4,
+    X5,
+    X6,
+    X7,
+    X8,
+    X9,
+    X10,
+    X11,
+    X12,
+    X13,
+    X14,
+    X15,
+    X16,
+    X17,
+    X18,
+    X19,
+    X20,
+    X21,
+    X22,
+    X23,
+    X24,
+    X25,
+    X26,
+    X27,
+    X28,
+    Fp,
+    Lr,
+    Sp,
+    D0,
+    D1,
+    D2,
+    D3,
+}
+
+// 32-bit arm registers plus the vfp doubles (printed as s2n at W32)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArmReg {
+    R0,
+    R1,
+    R2,
+    R3,
+    R4,
+    R5,
+    R6,
+    R7,
+    R8,
+    R9,
+    R10,
+    Fp,
+    R12,
+    Sp,
+    Lr,
+    Pc,
+    D0,
+    D1,
+    D2,
+    D3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    X86(X86Reg),
+    A64(A64Reg),
+    Arm(ArmReg),
+}
+
+impl Register {
+    pub fn is_float(&self) -> bool {
+        match *self {
+            Register::X86(r) => r as usize >= X86Reg::Xmm0 as usize,
+            Register::A64(r) => r as usize >= A64Reg::D0 as usize,
+            Register::Arm(r) => r as usize >= ArmReg::D0 as usize,
+        }
+    }
+
+    pub fn name(&self, width: Width) -> &'static str {
+        const X86_64: [&str; 16] = [
+            "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11",
+            "r12", "r13", "r14", "r15",
+        ];
+        const X86_32: [&str; 16] = [
+            "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d",
+            "r12d", "r13d", "r14d", "r15d",
+        ];
+        const X86_8: [&str; 16] = [
+            "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil", "r8b", "r9b", "r10b", "r11b",
+            "r12b", "r13b", "r14b", "r15b",
+        ];
+        const XMM: [&str; 4] = ["xmm0", "xmm1", "xmm2", "xmm3"];
+        const A64_X: [&str; 32] = [
+            "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12", "x13",
+            "x14", "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24", "x25",
+            "x26", "x27", "x28", "x29", "x30", "sp",
+        ];
+        const A64_W: [&str; 32] = [
+            "w0", "w1", "w2", "w3", "w4", "w5", "w6", "w7", "w8", "w9", "w10", "w11", "w12", "w13",
+            "w14", "w15", "w16", "w17", "w18", "w19", "w20", "w21", "w22", "w23", "w24", "w25",
+            "w26", "w27", "w28", "w29", "w30", "wsp",
+        ];
+        const A64_D: [&str; 4] = ["d0", "d1", "d2", "d3"];
+        const A64_S: [&str; 4] = ["s0", "s1", "s2", "s3"];
+        const ARM: [&str; 16] = [
+            "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", "r8", "r9", "r10", "fp", "r12", "sp",
+            "lr", "pc",
+        ];
+        const ARM_D: [&str; 4] = ["d0", "d1", "d2", "d3"];
+        const ARM_S: [&str; 4] = ["s0", "s2", "s4", "s6"];
+
+        match *self {
+            Register::X86(r) => {
+                let n = r as usize;
+                if n >= 16 {
+                    return XMM[n - 16];
+                }
+                match width {
+                    Width::W64 => X86_64[n],
+                    Width::W32 => X86_32[n],
+                    Width::W8 => X86_8[n],
+                }
+            }
+            Register::A64(r) => {
+                let n = r as usize;
+                if n >= 32 {
+                    return match width {
+                        Width::W64 => A64_D[n - 32],
+                        _ => A64_S[n - 32],
+                    };
+                }
+                match width {
+                    Width::W64 => A64_X[n],
+                    _ => A64_W[n],
+                }
+            }
+            Register::Arm(r) => {
+                let n = r as usize;
+                if n >= 16 {
+                    return match width {
+                        Width::W64 => ARM_D[n - 16],
+                        _ => ARM_S[n - 16],
+                    };
+                }
+                ARM[n]
+            }
+        }
+    }
+}
+
+// what a register is used for; every target maps every role
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegRole {
+    SyscallNr,
+    Arg0,
+    Arg1,
+    Arg2,
+    Arg3,
+    Arg4,
+    Arg5,
+    Return,
+    StackPointer,
+    FramePointer,
+    FloatScratch,
+    FloatTemp1,
+    FloatTemp2,
+}
+
+impl RegRole {
+    pub const ARGS: [RegRole; 6] = [
+        RegRole::Arg0,
+        RegRole::Arg1,
+        RegRole::Arg2,
+        RegRole::Arg3,
+        RegRole::Arg4,
+        RegRole::Arg5,
+    ];
+}
+
+// the instruction that enters the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trap {
+    Syscall,
+    Int80,
+    Svc,
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trap::Syscall => write!(f, "syscall"),
+            Trap::Int80 => write!(f, "int 0x80"),
+            Trap::Svc => write!(f, "svc 0"),
+        }
+    }
+}
+
+// how a target enters the kernel: the registers come from RegRole::SyscallNr
+// and RegRole::Arg0..Arg5, plus the trap instruction and syscall numbers
+pub struct SyscallAbi {
+    pub trap: Trap,
+    pub write: u32,
+    pub exit: u32,
+}
+
+fn linux_x86_64(role: RegRole) -> Register {
+    Register::X86(match role {
+        RegRole::SyscallNr => X86Reg::Rax,
+        RegRole::Arg0 => X86Reg::Rdi,
+        RegRole::Arg1 => X86Reg::Rsi,
+        RegRole::Arg2 => X86Reg::Rdx,
+        RegRole::Arg3 => X86Reg::R10,
+        RegRole::Arg4 => X86Reg::R8,
+        RegRole::Arg5 => X86Reg::R9,
+        RegRole::Return => X86Reg::Rax,
+        RegRole::StackPointer => X86Reg::Rsp,
+        RegRole::FramePointer => X86Reg::Rbp,
+        RegRole::FloatScratch => X86Reg::Xmm0,
+        RegRole::FloatTemp1 => X86Reg::Xmm1,
+        RegRole::FloatTemp2 => X86Reg::Xmm2,
+    })
+}
+
+fn linux_x86(role: RegRole) -> Register {
+    Register::X86(match role {
+        RegRole::SyscallNr => X86Reg::Rax,
+        RegRole::Arg0 => X86Reg::Rbx,
+        RegRole::Arg1 => X86Reg::Rcx,
+        RegRole::Arg2 => X86Reg::Rdx,
+        RegRole::Arg3 => X86Reg::Rsi,
+        RegRole::Arg4 => X86Reg::Rdi,
+        RegRole::Arg5 => X86Reg::Rbp,
+        RegRole::Return => X86Reg::Rax,
+        RegRole::StackPointer => X86Reg::Rsp,
+        RegRole::FramePointer => X86Reg::Rbp,
+        RegRole::FloatScratch => X86Reg::Xmm0,
+        RegRole::FloatTemp1 => X86Reg::Xmm1,
+        RegRole::FloatTemp2 => X86Reg::Xmm2,
+    })
+}
+
+fn linux_aarch64(role: RegRole) -> Register {
+    Register::A64(match role {
+        RegRole::SyscallNr => A64Reg::X8,
+        RegRole::Arg0 => A64Reg::X0,
+        RegRole::Arg1 => A64Reg::X1,
+        RegRole::Arg2 => A64Reg::X2,
+        RegRole::Arg3 => A64Reg::X3,
+        RegRole::Arg4 => A64Reg::X4,
+        RegRole::Arg5 => A64Reg::X5,
+        RegRole::Return => A64Reg::X0,
+        RegRole::StackPointer => A64Reg::Sp,
+        RegRole::FramePointer => A64Reg::Fp,
+        RegRole::FloatScratch => A64Reg::D0,
+        RegRole::FloatTemp1 => A64Reg::D1,
+        RegRole::FloatTemp2 => A64Reg::D2,
+    })
+}
+
+fn linux_arm(role: RegRole) -> Register {
+    Register::Arm(match role {
+        RegRole::SyscallNr => ArmReg::R7,
+        RegRole::Arg0 => ArmReg::R0,
+        RegRole::Arg1 => ArmReg::R1,
+        RegRole::Arg2 => ArmReg::R2,
+        RegRole::Arg3 => ArmReg::R3,
+        RegRole::Arg4 => ArmReg::R4,
+        RegRole::Arg5 => ArmReg::R5,
+        RegRole::Return => ArmReg::R0,
+        RegRole::StackPointer => ArmReg::Sp,
+        RegRole::FramePointer => ArmReg::Fp,
+        RegRole::FloatScratch => ArmReg::D0,
+        RegRole::FloatTemp1 => ArmReg::D1,
+        RegRole::FloatTemp2 => ArmReg::D2,
+    })
+}
+
 impl Target {
     pub fn new(bit: Bit, arch: Arch, os: OS) -> Self {
         Target { bit, arch, os }
     }
 
-    pub fn regs(&self) -> Option<HashMap<String, String>> {
+    // the machine this was compiled for, if it is one maker knows about
+    pub fn host() -> Option<Self> {
+        let (bit, arch) = if cfg!(target_arch = "x86_64") {
+            (Bit::X64, Arch::X86)
+        } else if cfg!(target_arch = "x86") {
+            (Bit::X32, Arch::X86)
+        } else if cfg!(target_arch = "aarch64") {
+            (Bit::X64, Arch::Arm)
+        } else if cfg!(target_arch = "arm") {
+            (Bit::X32, Arch::Arm)
+        } else {
+            return None;
+        };
+        let os = if cfg!(target_os = "linux") {
+            OS::Linux
+        } else if cfg!(target_os = "windows") {
+            OS::Windows
+        } else if cfg!(target_os = "macos") {
+            OS::Mac
+        } else {
+            return None;
+        };
+        Some(Target { bit, arch, os })
+    }
+
+    // native register width
+    pub fn word(&self) -> Width {
+        match self.bit {
+            Bit::X32 => Width::W32,
+            Bit::X64 => Width::W64,
+        }
+    }
+
+    // return address register that a nested call would clobber
+    pub fn link_reg(&self) -> Option<Register> {
+        match self.arch {
+            Arch::X86 => None,
+            Arch::Arm => match self.bit {
+                Bit::X64 => Some(Register::A64(A64Reg::Lr)),
+                Bit::X32 => Some(Register::Arm(ArmReg::Lr)),
+            },
+        }
+    }
+
+    pub fn reg(&self, role: RegRole) -> Result<Register, MakeError> {
         match (&self.os, &self.arch, &self.bit) {
-            (OS::Linux, Arch::X86, Bit::X64) => {
-                let mut map = HashMap::new();
-                map.insert("mov".to_string(), "mov".to_string());
-                map.insert("string".to_string(), "rsi".to_string());
-                map.insert("Dest Index Register (i64)".to_string(), "rbx".to_string());
-                map.insert("Syscall Write".to_string(), "1".to_string());
-                map.insert("Syscall".to_string(), "syscall".to_string());
-                map.insert("i32".to_string(), "ebx".to_string());
-                map.insert("i64".to_string(), "rbx".to_string());
-                map.insert("f32".to_string(), "xmm0".to_string());
-                map.insert("f64".to_string(), "xmm1".to_string());
-                map.insert("rsi".to_string(), "rsi".to_string());
-                map.insert("rdi".to_string(), "rdi".to_string());
-                map.insert("rsp".to_string(), "rsp".to_string());
-                map.insert("rbp".to_string(), "rbp".to_string());
-                map.insert("rax".to_string(), "rax".to_string());
-                map.insert("rbx".to_string(), "rbx".to_string());
-                map.insert("rcx".to_string(), "rcx".to_string());
-                map.insert("rdx".to_string(), "rdx".to_string());
-                //map.insert("k", v)
-                Some(map)
-            }
-            (OS::Linux, Arch::X86, Bit::X32) => {
-                let mut map = HashMap::new();
-
-                map.insert("mov".to_string(), "mov".to_string());
-                map.insert("string".to_string(), "esi".to_string());
-                map.insert("Dest Index Register (i64)".to_string(), "ebx".to_string());
-                map.insert("Syscall Write".to_string(), "1".to_string());
-                map.insert("Syscall".to_string(), "syscall".to_string());
-                map.insert("i32".to_string(), "ebx".to_string());
-                map.insert("i64".to_string(), "ebx".to_string());
-                map.insert("f32".to_string(), "xmm0".to_string());
-                map.insert("f64".to_string(), "xmm1".to_string());
-                map.insert("rsi".to_string(), "esi".to_string());
-                map.insert("rdi".to_string(), "edi".to_string());
-                map.insert("rsp".to_string(), "esp".to_string());
-                map.insert("rbp".to_string(), "ebp".to_string());
-                map.insert("rax".to_string(), "eax".to_string());
-                map.insert("rbx".to_string(), "ebx".to_string());
-                map.insert("rcx".to_string(), "ecx".to_string());
-                map.insert("rdx".to_string(), "edx".to_string());
-                map.insert("exit code".to_string(), "1".to_string());
-
-                Some(map)
-            }
-            (OS::Linux, Arch::Arm, Bit::X64) => {
-                let mut map = HashMap::new();
-                map.insert("mov".to_string(), "mov".to_string());
-                map.insert("string".to_string(), "x0".to_string());
-                map.insert("Dest Index Register (i64)".to_string(), "x1".to_string());
-                map.insert("Syscall Write".to_string(), "64".to_string());
-                map.insert("Syscall".to_string(), "svc 0".to_string());
-                map.insert("i32".to_string(), "w1".to_string());
-                map.insert("i64".to_string(), "x1".to_string());
-                map.insert("f32".to_string(), "s0".to_string());
-                map.insert("f64".to_string(), "d0".to_string());
-                map.insert("rsi".to_string(), "x0".to_string());
-                map.insert("rdi".to_string(), "x1".to_string());
-                map.insert("rsp".to_string(), "sp".to_string());
-                map.insert("rbp".to_string(), "fp".to_string());
-                map.insert("rax".to_string(), "x8".to_string());
-                map.insert("rbx".to_string(), "x1".to_string());
-                map.insert("rdx".to_string(), "x2".to_string());
-                map.insert("rcx".to_string(), "x3".to_string());
-                map.insert("exit code".to_string(), "60".to_string());
-
-                Some(map)
-            }
-            (OS::Linux, Arch::Arm, Bit::X32) => {
-                let mut map = HashMap::new();
-                map.insert("mov".to_string(), "mov".to_string());
-                map.insert("string".to_string(), "r0".to_string());
-                map.insert("Dest Index Register (i64)".to_string(), "r1".to_string());
-                map.insert("Syscall Write".to_string(), "4".to_string());
-                map.insert("Syscall".to_string(), "svc 0".to_string());
-                map.insert("i32".to_string(), "r1".to_string());
-                map.insert("i64".to_string(), "r2".to_string());
-                map.insert("f32".to_string(), "s0".to_string());
-                map.insert("f64".to_string(), "d0".to_string());
-                map.insert("rsi".to_string(), "r0".to_string());
-                map.insert("rdi".to_string(), "r1".to_string());
-                map.insert("rsp".to_string(), "sp".to_string());
-                map.insert("rbp".to_string(), "fp".to_string());
-                map.insert("rax".to_string(), "r7".to_string());
-                map.insert("rbx".to_string(), "r3".to_string());
-                map.insert("rdx".to_string(), "r2".to_string());
-                map.insert("rcx".to_string(), "r3".to_string());
-                map.insert("exit code".to_string(), "1".to_string());
-
-                Some(map)
-            }
-            (OS::Windows, Arch::X86, Bit::X64) => {
-                let mut map = HashMap::new();
-                map.insert("mov".to_string(), "mov".to_string());
-                map.insert("string".to_string(), "rsi".to_string());
-                map.insert("Dest Index Register (i64)".to_string(), "rbx".to_string());
-                map.insert("Syscall Write".to_string(), "0x80".to_string());
-                map.insert("Syscall".to_string(), "syscall".to_string());
-                map.insert("i32".to_string(), "ebx".to_string());
-                map.insert("i64".to_string(), "rbx".to_string());
-                map.insert("f32".to_string(), "xmm0".to_string());
-                map.insert("f64".to_string(), "xmm1".to_string());
-                map.insert("rsi".to_string(), "rsi".to_string());
-                map.insert("rdi".to_string(), "rdi".to_string());
-                map.insert("rsp".to_string(), "rsp".to_string());
-                map.insert("rbp".to_string(), "rbp".to_string());
-                map.insert("rax".to_string(), "rax".to_string());
-                map.insert("rbx".to_string(), "rbx".to_string());
-                map.insert("rcx".to_string(), "rcx".to_string());
-                map.insert("rdx".to_string(), "rdx".to_string());
-                map.insert("exit code".to_string(), "0x0".to_string());
-
-                Some(map)
-            }
+            (OS::Linux, Arch::X86, Bit::X64) => Ok(linux_x86_64(role)),
+            (OS::Linux, Arch::X86, Bit::X32) => Ok(linux_x86(role)),
+            (OS::Linux, Arch::Arm, Bit::X64) => Ok(linux_aarch64(role)),
+            (OS::Linux, Arch::Arm, Bit::X32) => Ok(linux_arm(role)),
+            // windows has no stable syscall interface and mac isn't done yet
+            _ => Err(MakeError::UnsupportedTarget(self.to_string())),
+        }
+    }
 
-            (OS::Windows, Arch::X86, Bit::X32) => {
-                let mut map = HashMap::new();
-                map.insert("mov".to_string(), "mov".to_string());
-                map.insert("string".to_string(), "esi".to_string());
-                map.insert("Dest Index Register (i64)".to_string(), "ebx".to_string());
-                map.insert("Syscall Write".to_string(), "0x80".to_string());
-                map.insert("Syscall".to_string(), "syscall".to_string());
-                map.insert("i32".to_string(), "ebx".to_string());
-                map.insert("i64".to_string(), "ebx".to_string());
-                map.insert("f32".to_string(), "xmm0".to_string());
-                map.insert("f64".to_string(), "xmm1".to_string());
-                map.insert("rsi".to_string(), "esi".to_string());
-                map.insert("rdi".to_string(), "edi".to_string());
-                map.insert("rsp".to_string(), "esp".to_string());
-                map.insert("rbp".to_string(), "ebp".to_string());
-                map.insert("rax".to_string(), "eax".to_string());
-                map.insert("rbx".to_string(), "ebx".to_string());
-                map.insert("rcx".to_string(), "ecx".to_string());
-                map.insert("rdx".to_string(), "edx".to_string());
-                map.insert("exit code".to_string(), "0x0".to_string());
-
-                Some(map)
-            }
+    pub fn syscall_abi(&self) -> Result<SyscallAbi, MakeError> {
+        match (&self.os, &self.arch, &self.bit) {
+            (OS::Linux, Arch::X86, Bit::X64) => Ok(SyscallAbi {
+                trap: Trap::Syscall,
+                write: 1,
+                exit: 60,
+            }),
+            (OS::Linux, Arch::X86, Bit::X32) => Ok(SyscallAbi {
+                trap: Trap::Int80,
+                write: 4,
+                exit: 1,
+            }),
+            (OS::Linux, Arch::Arm, Bit::X64) => Ok(SyscallAbi {
+                trap: Trap::Svc,
+                write: 64,
+                exit: 93,
+            }),
+            (OS::Linux, Arch::Arm, Bit::X32) => Ok(SyscallAbi {
+                trap: Trap::Svc,
+                write: 4,
+                exit: 1,
+            }),
+            _ => Err(MakeError::UnsupportedTarget(self.to_string())),
+        }
+    }
+}
 
-            (OS::Windows, Arch::Arm, Bit::X64) => {
-                let mut map = HashMap::new();
-                map.insert("mov".to_string(), "mov".to_string());
-                map.insert("string".to_string(), "r0".to_string());
-                map.insert("Dest Index Register (i64)".to_string(), "r1".to_string());
-                map.insert("Syscall Write".to_string(), "0x80".to_string());
-                map.insert("Syscall".to_string(), "svc 0".to_string());
-                map.insert("i32".to_string(), "r1".to_string());
-                map.insert("i64".to_string(), "r2".to_string());
-                map.insert("f32".to_string(), "s0".to_string());
-                map.insert("f64".to_string(), "d0".to_string());
-                map.insert("rsi".to_string(), "r0".to_string());
-                map.insert("rdi".to_string(), "r1".to_string());
-                map.insert("rsp".to_string(), "sp".to_string());
-                map.insert("rbp".to_string(), "fp".to_string());
-                map.insert("rax".to_string(), "r7".to_string());
-                map.insert("rbx".to_string(), "r3".to_string());
-                map.insert("rdx".to_string(), "r2".to_string());
-                map.insert("rcx".to_string(), "r3".to_string());
-                map.insert("exit code".to_string(), "0x1".to_string());
-
-                Some(map)
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            (OS::Windows, Arch::Arm, Bit::X32) => {
-                let mut map = HashMap::new();
-                map.insert("mov".to_string(), "mov".to_string());
-                map.insert("string".to_string(), "r0".to_string());
-                map.insert("Dest Index Register (i64)".to_string(), "r1".to_string());
-                map.insert("Syscall Write".to_string(), "0x80".to_string());
-                map.insert("Syscall".to_string(), "svc 0".to_string());
-                map.insert("i32".to_string(), "r1".to_string());
-                map.insert("i64".to_string(), "r2".to_string());
-                map.insert("f32".to_string(), "s0".to_string());
-                map.insert("f64".to_string(), "d0".to_string());
-                map.insert("rsi".to_string(), "r0".to_string());
-                map.insert("rdi".to_string(), "r1".to_string());
-                map.insert("rsp".to_string(), "sp".to_string());
-                map.insert("rbp".to_string(), "fp".to_string());
-                map.insert("rax".to_string(), "r7".to_string());
-                map.insert("rbx".to_string(), "r3".to_string());
-                map.insert("rdx".to_string(), "r2".to_string());
-                map.insert("rcx".to_string(), "r3".to_string());
-                map.insert("exit code".to_string(), "0x1".to_string());
-
-                Some(map)
-            }
+    #[test]
+    fn triples_round_trip() {
+        for triple in [
+            "x86_64-linux",
+            "i686-linux",
+            "aarch64-linux",
+            "armv7-linux",
+            "x86_64-windows",
+            "aarch64-macos",
+        ] {
+            let target: Target = triple.parse().unwrap();
+            assert_eq!(target.to_string(), triple);
+        }
+    }
+
+    #[test]
+    fn aliases_and_full_triples_parse() {
+        let parse = |triple: &str| triple.parse::<Target>().unwrap();
+        assert_eq!(
+            parse("x86_64-unknown-linux-gnu"),
+            Target::new(Bit::X64, Arch::X86, OS::Linux)
+        );
+        assert_eq!(
+            parse("i386-linux"),
+            Target::new(Bit::X32, Arch::X86, OS::Linux)
+        );
+        assert_eq!(
+            parse("armv7-unknown-linux-gnueabihf"),
+            Target::new(Bit::X32, Arch::Arm, OS::Linux)
+        );
+        assert_eq!(
+            parse("arm64-apple-darwin"),
+            Target::new(Bit::X64, Arch::Arm, OS::Mac)
+        );
+        for bad in ["", "x86_64", "mips-linux", "x86_64-plan9"] {
+            assert!(matches!(
+                bad.parse::<Target>(),
+                Err(MakeError::UnknownTarget(t)) if t == bad
+            ));
+        }
+    }
 
-            _ => None,
+    #[test]
+    fn linux_roles_map_to_distinct_registers() {
+        let all = [
+            RegRole::SyscallNr,
+            RegRole::Arg0,
+            RegRole::Arg1,
+            RegRole::Arg2,
+            RegRole::Arg3,
+            RegRole::Arg4,
+            RegRole::Arg5,
+            RegRole::Return,
+            RegRole::StackPointer,
+            RegRole::FramePointer,
+            RegRole::FloatScratch,
+            RegRole::FloatTemp1,
+            RegRole::FloatTemp2,
+        ];
+        for triple in ["x86_64-linux", "i686-linux", "aarch64-linux", "armv7-linux"] {
+            let target: Target = triple.parse().unwrap();
+            for role in all {
+                assert!(target.reg(role).is_ok(), "{} {:?}", triple, role);
+            }
+            // the number and the six arguments are all live at the trap
+            let mut live = vec![target.reg(RegRole::SyscallNr).unwrap()];
+            live.extend(RegRole::ARGS.map(|role| target.reg(role).unwrap()));
+            for (i, reg) in live.iter().enumerate() {
+                assert!(!live[i + 1..].contains(reg), "{} {:?}", triple, reg);
+            }
+            assert!(!live.contains(&target.reg(RegRole::StackPointer).unwrap()));
+            // the float formatter holds all three at once
+            let floats = [
+                RegRole::FloatScratch,
+                RegRole::FloatTemp1,
+                RegRole::FloatTemp2,
+            ]
+            .map(|role| target.reg(role).unwrap());
+            assert!(floats[0] != floats[1] && floats[1] != floats[2] && floats[0] != floats[2]);
+            assert!(target.syscall_abi().is_ok());
+        }
+        for triple in ["x86_64-windows", "aarch64-macos"] {
+            let target: Target = triple.parse().unwrap();
+            assert!(target.reg(RegRole::Arg0).is_err() && target.syscall_abi().is_err());
         }
     }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn host_is_detected() {
+        assert_eq!(Target::host().unwrap().to_string(), "x86_64-linux");
+    }
 }
@@ -0,0 +1,278 @@
+use crate::{
+    arch::{Arch, Bit, Target, OS},
+    coreasm::CoreAsm,
+    emit::Syntax,
+    error::{BuildError, MakeError},
+    maker::maker_with_syntax,
+};
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+// the assembler and linker for one target, without the file names
+struct Toolchain {
+    assembler: String,
+    assembler_args: Vec<&'static str>,
+    linker: String,
+    linker_args: Vec<&'static str>,
+    // what the assembler expects the source to be called
+    extension: &'static str,
+}
+
+// binutils for another architecture carry its triple as a prefix
+fn host_prefix(arch: Arch, bit: Bit) -> &'static str {
+    let native = match (arch, bit) {
+        (Arch::X86, Bit::X64) => cfg!(target_arch = "x86_64"),
+        // the host's binutils take --32 as well as making i386 code natively
+        (Arch::X86, Bit::X32) => cfg!(any(target_arch = "x86", target_arch = "x86_64")),
+        (Arch::Arm, Bit::X64) => cfg!(target_arch = "aarch64"),
+        (Arch::Arm, Bit::X32) => cfg!(target_arch = "arm"),
+    };
+    match (native, arch, bit) {
+        (true, _, _) => "",
+        (false, Arch::X86, _) => "x86_64-linux-gnu-",
+        (false, Arch::Arm, Bit::X64) => "aarch64-linux-gnu-",
+        (false, Arch::Arm, Bit::X32) => "arm-linux-gnueabi-",
+    }
+}
+
+fn toolchain(target: &Target, syntax: Syntax) -> Result<Toolchain, MakeError> {
+    if target.os != OS::Linux {
+        return Err(MakeError::UnsupportedTarget(target.to_string()));
+    }
+    let prefix = host_prefix(target.arch, target.bit);
+    let (assembler, assembler_args, extension) = match (syntax, target.bit) {
+        (Syntax::Nasm, Bit::X64) => ("nasm".to_string(), vec!["-f", "elf64"], "asm"),
+        (Syntax::Nasm, Bit::X32) => ("nasm".to_string(), vec!["-f", "elf32"], "asm"),
+        (_, bit) => {
+            let args = match (target.arch, bit) {
+                (Arch::X86, Bit::X64) => vec!["--64"],
+                (Arch::X86, Bit::X32) => vec!["--32"],
+                (Arch::Arm, _) => vec![],
+            };
+            (format!("{}as", prefix), args, "s")
+        }
+    };
+    let linker_args = match (target.arch, target.bit) {
+        (Arch::X86, Bit::X32) => vec!["-m", "elf_i386"],
+        _ => vec![],
+    };
+    Ok(Toolchain {
+        assembler,
+        assembler_args,
+        linker: format!("{}ld", prefix),
+        linker_args,
+        extension,
+    })
+}
+
+// runs a tool to completion, keeping its stderr when it fails
+fn run<S: AsRef<OsStr>>(tool: &str, args: &[S]) -> Result<(), BuildError> {
+    let output = Command::new(tool)
+        .args(args)
+        .output()
+        .map_err(|error| BuildError::Spawn {
+            tool: tool.to_string(),
+            error,
+        })?;
+    if !output.status.success() {
+        return Err(BuildError::Tool {
+            tool: tool.to_string(),
+            status: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(())
+}
+
+// the source and object build writes next to `out`, in that order
+pub fn intermediates(
+    target: &Target,
+    syntax: Syntax,
+    out: &Path,
+) -> Result<[PathBuf; 2], MakeError> {
+    let tools = toolchain(target, syntax)?;
+    Ok([out.with_extension(tools.extension), out.with_extension("o")])
+}
+
+// assembles and links a program into the executable `out`, with the source
+// and object left beside it (out.asm or out.s, and out.o)
+pub fn build(core_asm: &CoreAsm, target: Target, out: &Path) -> Result<PathBuf, BuildError> {
+    let syntax = Syntax::default_for(&target);
+    build_with_syntax(core_asm, target, syntax, out)
+}
+
+// build, written in the given syntax and run through the matching assembler
+pub fn build_with_syntax(
+    core_asm: &CoreAsm,
+    target: Target,
+    syntax: Syntax,
+    out: &Path,
+) -> Result<PathBuf, BuildError> {
+    let tools = toolchain(&target, syntax)?;
+    let [source_path, object_path] = intermediates(&target, syntax, out)?;
+    // an `out` like prog.s would be written as the source, then linked over
+    if let Some(path) = [&source_path, &object_path].into_iter().find(|p| *p == out) {
+        return Err(BuildError::Overwrite(path.clone()));
+    }
+    let source = maker_with_syntax(core_asm, target, syntax)?;
+    fs::write(&source_path, source)?;
+
+    let mut args: Vec<&OsStr> = tools.assembler_args.iter().map(OsStr::new).collect();
+    args.extend([
+        source_path.as_os_str(),
+        OsStr::new("-o"),
+        object_path.as_os_str(),
+    ]);
+    run(&tools.assembler, &args)?;
+
+    let mut args: Vec<&OsStr> = tools.linker_args.iter().map(OsStr::new).collect();
+    args.extend([object_path.as_os_str(), OsStr::new("-o"), out.as_os_str()]);
+    run(&tools.linker, &args)?;
+    Ok(out.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coreasm::{Print, PrintToken, Types, VarValue};
+
+    fn hello() -> CoreAsm {
+        let mut asm = CoreAsm::new();
+        asm.data.mkvar(
+            "name".to_string(),
+            Types::String,
+            VarValue::String("joy".to_string()),
+        );
+        let mut print = Print::new();
+        print.add_token(PrintToken::Text("Hello ".to_string()));
+        print.add_token(PrintToken::Variable("name".to_string()));
+        print.add_token(PrintToken::Newline);
+        asm.prints.push(print);
+        asm.exit(true);
+        asm
+    }
+
+    #[test]
+    fn failures_keep_the_tools_stderr() {
+        match run("sh", &["-c", "echo 'bad operand' >&2; exit 3"]) {
+            Err(BuildError::Tool {
+                tool,
+                status,
+                stderr,
+            }) => {
+                assert_eq!(tool, "sh");
+                assert_eq!(status, Some(3));
+                assert_eq!(stderr, "bad operand\n");
+            }
+            other => panic!("expected a tool error, got {:?}", other),
+        }
+        assert!(matches!(
+            run("coreasm-no-such-tool", &["x"]),
+            Err(BuildError::Spawn { .. })
+        ));
+    }
+
+    #[test]
+    fn toolchain_follows_target_and_syntax() {
+        let i386 = Target::new(Bit::X32, Arch::X86, OS::Linux);
+        let tools = toolchain(&i386, Syntax::Nasm).unwrap();
+        assert_eq!(tools.assembler, "nasm");
+        assert_eq!(tools.assembler_args, ["-f", "elf32"]);
+        assert_eq!(tools.linker_args, ["-m", "elf_i386"]);
+        let tools = toolchain(&i386, Syntax::GasAtt).unwrap();
+        assert!(tools.assembler.ends_with("as"));
+        assert_eq!(tools.extension, "s");
+        // the host's own as and ld take 32-bit x86 on x86 hosts, cross tools otherwise
+        let native = cfg!(any(target_arch = "x86", target_arch = "x86_64"));
+        assert_eq!(tools.assembler == "as", native);
+        assert_eq!(tools.linker == "ld", native);
+        let x86_64 = Target::new(Bit::X64, Arch::X86, OS::Linux);
+        let tools = toolchain(&x86_64, Syntax::GasIntel).unwrap();
+        let prefix = if cfg!(target_arch = "x86_64") {
+            ""
+        } else {
+            "x86_64-linux-gnu-"
+        };
+        assert_eq!(tools.assembler, format!("{}as", prefix));
+        assert_eq!(tools.linker, format!("{}ld", prefix));
+        let armv7 = Target::new(Bit::X32, Arch::Arm, OS::Linux);
+        let tools = toolchain(&armv7, Syntax::GasAtt).unwrap();
+        let prefix = if cfg!(target_arch = "arm") {
+            ""
+        } else {
+            "arm-linux-gnueabi-"
+        };
+        assert_eq!(tools.linker, format!("{}ld", prefix));
+        let windows = Target::new(Bit::X64, Arch::X86, OS::Windows);
+        assert!(toolchain(&windows, Syntax::Nasm).is_err());
+    }
+
+    // registers and keywords as variable names, in both GNU as syntaxes
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    #[ignore = "needs GNU as and ld"]
+    fn names_survive_the_assembler() {
+        let mut asm = CoreAsm::new();
+        let mut print = Print::new();
+        for (i, name) in ["offset", "byte", "rax", "esi", "sp"].iter().enumerate() {
+            asm.data
+                .mkvar(name.to_string(), Types::I32, VarValue::I32(i as i32));
+            print.add_token(PrintToken::Variable(name.to_string()));
+        }
+        print.add_token(PrintToken::Newline);
+        asm.prints.push(print);
+        asm.exit(true);
+        let dir = std::env::temp_dir().join(format!("coreasm-names-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = Target::new(Bit::X64, Arch::X86, OS::Linux);
+        for (syntax, file) in [(Syntax::GasIntel, "intel"), (Syntax::GasAtt, "att")] {
+            let exe = build_with_syntax(&asm, target, syntax, &dir.join(file)).unwrap();
+            let output = Command::new(&exe).output().unwrap();
+            assert_eq!(
+                String::from_utf8_lossy(&output.stdout),
+                "01234\n",
+                "{syntax:?}"
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn outputs_named_like_intermediates_are_refused() {
+        let target = Target::new(Bit::X64, Arch::X86, OS::Linux);
+        let dir = std::env::temp_dir().join(format!("coreasm-refused-{}", std::process::id()));
+        assert_eq!(
+            intermediates(&target, Syntax::Nasm, &dir.join("prog")).unwrap(),
+            [dir.join("prog.asm"), dir.join("prog.o")]
+        );
+        for (syntax, name) in [(Syntax::GasAtt, "prog.s"), (Syntax::Nasm, "prog.o")] {
+            let out = dir.join(name);
+            assert!(matches!(
+                build_with_syntax(&hello(), target, syntax, &out),
+                Err(BuildError::Overwrite(path)) if path == out
+            ));
+        }
+        // refused before anything was written
+        assert!(!dir.exists());
+    }
+
+    // needs binutils, so it only runs when asked for
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    #[ignore = "needs GNU as and ld"]
+    fn builds_and_runs_with_gnu_as() {
+        let dir = std::env::temp_dir().join(format!("coreasm-build-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = Target::new(Bit::X64, Arch::X86, OS::Linux);
+        let exe = build_with_syntax(&hello(), target, Syntax::GasAtt, &dir.join("hello"))
+            .unwrap_or_else(|e| panic!("{}", e));
+        assert!(dir.join("hello.s").exists() && dir.join("hello.o").exists());
+        let output = Command::new(&exe).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello joy\n");
+    }
+}
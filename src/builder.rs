@@ -0,0 +1,526 @@
+// a shorter way to write a CoreAsm:
+//
+//   let asm = CoreAsm::builder()
+//       .string("name", "joy")
+//       .print(|p| p.text("Hello ").var("name").nl())
+//       .i32("n", 7)
+//       .if_else(
+//           Condition::new("n", Compare::Lt, 10),
+//           |then| then.print(|p| p.text("small").nl()),
+//           |otherwise| otherwise.print(|p| p.var("n").nl()),
+//       )
+//       .exit(0)
+//       .build()?;
+//
+// types come from the values, and nothing is checked until build, which
+// hands back the first thing CoreAsm::validate finds wrong
+use crate::{
+    coreasm::{Condition, CoreAsm, Print, PrintToken, Statement, Types, Value, Var, VarValue},
+    error::MakeError,
+};
+
+#[derive(Default)]
+pub struct Builder {
+    core_asm: CoreAsm,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Builder {
+            core_asm: CoreAsm::new(),
+        }
+    }
+
+    // a variable of whatever type the value is
+    pub fn var(self, name: impl Into<String>, value: VarValue) -> Self {
+        let var_type = value.types();
+        self.declare(name, var_type, value)
+    }
+
+    // a variable with its type spelled out; build fails if the value isn't
+    // of that type
+    pub fn declare(mut self, name: impl Into<String>, var_type: Types, value: VarValue) -> Self {
+        // pushed rather than mkvar'd, so a second declaration is an error
+        // instead of replacing the first
+        self.core_asm
+            .data
+            .variables
+            .push((name.into(), Var { var_type, value }));
+        self
+    }
+
+    pub fn string(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.var(name, VarValue::String(value.into()))
+    }
+
+    pub fn i32(self, name: impl Into<String>, value: i32) -> Self {
+        self.var(name, VarValue::I32(value))
+    }
+
+    pub fn i64(self, name: impl Into<String>, value: i64) -> Self {
+        self.var(name, VarValue::I64(value))
+    }
+
+    pub fn f32(self, name: impl Into<String>, value: f32) -> Self {
+        self.var(name, VarValue::F32(value))
+    }
+
+    pub fn f64(self, name: impl Into<String>, value: f64) -> Self {
+        self.var(name, VarValue::F64(value))
+    }
+
+    // one print, one write
+    pub fn print(mut self, tokens: impl FnOnce(PrintBuilder) -> PrintBuilder) -> Self {
+        self.core_asm.statements.push(print(tokens));
+        self
+    }
+
+    pub fn if_then(mut self, condition: Condition, then: impl FnOnce(Block) -> Block) -> Self {
+        let statement = if_else(condition, then, |otherwise| otherwise);
+        self.core_asm.statements.push(statement);
+        self
+    }
+
+    pub fn if_else(
+        mut self,
+        condition: Condition,
+        then: impl FnOnce(Block) -> Block,
+        otherwise: impl FnOnce(Block) -> Block,
+    ) -> Self {
+        let statement = if_else(condition, then, otherwise);
+        self.core_asm.statements.push(statement);
+        self
+    }
+
+    pub fn while_loop(mut self, condition: Condition, body: impl FnOnce(Block) -> Block) -> Self {
+        let statement = Statement::While {
+            condition,
+            body: body(Block::default()).statements,
+        };
+        self.core_asm.statements.push(statement);
+        self
+    }
+
+    // `var` counts from `from` towards `to` (which it stops short of) by `step`
+    pub fn for_loop(
+        mut self,
+        var: impl Into<String>,
+        from: impl Into<Value>,
+        to: impl Into<Value>,
+        step: i64,
+        body: impl FnOnce(Block) -> Block,
+    ) -> Self {
+        let statement = for_loop(var, from, to, step, body);
+        self.core_asm.statements.push(statement);
+        self
+    }
+
+    // var = value, for an i32 or i64 variable and another one or a constant
+    pub fn set(mut self, var: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.core_asm.statements.push(Statement::Set {
+            var: var.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    // var += value
+    pub fn add(mut self, var: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.core_asm.statements.push(Statement::Add {
+            var: var.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    // var -= value
+    pub fn sub(mut self, var: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.core_asm.statements.push(Statement::Sub {
+            var: var.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    pub fn exit(mut self, code: u8) -> Self {
+        self.core_asm.exit_with(code);
+        self
+    }
+
+    pub fn build(self) -> Result<CoreAsm, MakeError> {
+        self.core_asm.validate()?;
+        Ok(self.core_asm)
+    }
+}
+
+fn print(tokens: impl FnOnce(PrintBuilder) -> PrintBuilder) -> Statement {
+    let print = tokens(PrintBuilder {
+        print: Print::new(),
+    });
+    Statement::Print(print.print)
+}
+
+fn if_else(
+    condition: Condition,
+    then: impl FnOnce(Block) -> Block,
+    otherwise: impl FnOnce(Block) -> Block,
+) -> Statement {
+    Statement::If {
+        condition,
+        then: then(Block::default()).statements,
+        otherwise: otherwise(Block::default()).statements,
+    }
+}
+
+fn for_loop(
+    var: impl Into<String>,
+    from: impl Into<Value>,
+    to: impl Into<Value>,
+    step: i64,
+    body: impl FnOnce(Block) -> Block,
+) -> Statement {
+    Statement::For {
+        var: var.into(),
+        from: from.into(),
+        to: to.into(),
+        step,
+        body: body(Block::default()).statements,
+    }
+}
+
+// the statements inside an if or a loop
+#[derive(Default)]
+pub struct Block {
+    statements: Vec<Statement>,
+}
+
+impl Block {
+    pub fn print(mut self, tokens: impl FnOnce(PrintBuilder) -> PrintBuilder) -> Self {
+        self.statements.push(print(tokens));
+        self
+    }
+
+    pub fn if_then(mut self, condition: Condition, then: impl FnOnce(Block) -> Block) -> Self {
+        self.statements
+            .push(if_else(condition, then, |otherwise| otherwise));
+        self
+    }
+
+    pub fn if_else(
+        mut self,
+        condition: Condition,
+        then: impl FnOnce(Block) -> Block,
+        otherwise: impl FnOnce(Block) -> Block,
+    ) -> Self {
+        self.statements.push(if_else(condition, then, otherwise));
+        self
+    }
+
+    pub fn while_loop(mut self, condition: Condition, body: impl FnOnce(Block) -> Block) -> Self {
+        self.statements.push(Statement::While {
+            condition,
+            body: body(Block::default()).statements,
+        });
+        self
+    }
+
+    pub fn for_loop(
+        mut self,
+        var: impl Into<String>,
+        from: impl Into<Value>,
+        to: impl Into<Value>,
+        step: i64,
+        body: impl FnOnce(Block) -> Block,
+    ) -> Self {
+        self.statements.push(for_loop(var, from, to, step, body));
+        self
+    }
+
+    pub fn set(mut self, var: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.statements.push(Statement::Set {
+            var: var.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    pub fn add(mut self, var: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.statements.push(Statement::Add {
+            var: var.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    pub fn sub(mut self, var: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.statements.push(Statement::Sub {
+            var: var.into(),
+            value: value.into(),
+        });
+        self
+    }
+
+    pub fn break_loop(mut self) -> Self {
+        self.statements.push(Statement::Break);
+        self
+    }
+
+    pub fn continue_loop(mut self) -> Self {
+        self.statements.push(Statement::Continue);
+        self
+    }
+}
+
+pub struct PrintBuilder {
+    print: Print,
+}
+
+impl PrintBuilder {
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.print.add_token(PrintToken::Text(text.into()));
+        self
+    }
+
+    pub fn var(mut self, name: impl Into<String>) -> Self {
+        self.print.add_token(PrintToken::Variable(name.into()));
+        self
+    }
+
+    // an f32 or f64 variable with `precision` digits after the point
+    pub fn float(mut self, name: impl Into<String>, precision: u32) -> Self {
+        self.print.add_token(PrintToken::Float {
+            var: name.into(),
+            precision,
+        });
+        self
+    }
+
+    pub fn nl(mut self) -> Self {
+        self.print.add_token(PrintToken::Newline);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arch::{Arch, Bit, Target, OS},
+        coreasm::Compare,
+        maker::maker,
+    };
+
+    #[test]
+    fn builds_what_mkvar_would() {
+        let built = CoreAsm::builder()
+            .string("name", "joy")
+            .i32("answer", 42)
+            .f64("pi", 3.25)
+            .print(|p| p.text("Hello ").var("name").nl())
+            .print(|p| p.var("answer").text(" ").float("pi", 2).nl())
+            .exit(0)
+            .build()
+            .unwrap();
+
+        let mut asm = CoreAsm::new();
+        asm.data.mkvar(
+            "name".to_string(),
+            Types::String,
+            VarValue::String("joy".to_string()),
+        );
+        asm.data
+            .mkvar("answer".to_string(), Types::I32, VarValue::I32(42));
+        asm.data
+            .mkvar("pi".to_string(), Types::F64, VarValue::F64(3.25));
+        let print = asm.add_print();
+        print.add_token(PrintToken::Text("Hello ".to_string()));
+        print.add_token(PrintToken::Variable("name".to_string()));
+        print.add_token(PrintToken::Newline);
+        let print = asm.add_print();
+        print.add_token(PrintToken::Variable("answer".to_string()));
+        print.add_token(PrintToken::Text(" ".to_string()));
+        print.add_token(PrintToken::Float {
+            var: "pi".to_string(),
+            precision: 2,
+        });
+        print.add_token(PrintToken::Newline);
+        asm.exit(true);
+
+        let target = Target::new(Bit::X64, Arch::X86, OS::Linux);
+        assert_eq!(maker(&built, target).unwrap(), maker(&asm, target).unwrap());
+    }
+
+    #[test]
+    fn ifs_nest_in_blocks() {
+        let asm = CoreAsm::builder()
+            .i64("n", -3)
+            .if_else(
+                Condition::new("n", Compare::Lt, 0),
+                |then| {
+                    then.print(|p| p.text("negative"))
+                        .if_then(Condition::new("n", Compare::Lt, 5).unsigned(), |then| {
+                            then.print(|p| p.text(" and small"))
+                        })
+                },
+                |otherwise| otherwise.print(|p| p.var("n")),
+            )
+            .build()
+            .unwrap();
+        let [Statement::If {
+            condition,
+            then,
+            otherwise,
+        }] = asm.statements.as_slice()
+        else {
+            panic!("expected one if");
+        };
+        assert_eq!(condition.right, Value::Const(0));
+        assert!(matches!(
+            then.as_slice(),
+            [Statement::Print(_), Statement::If { condition, otherwise, .. }]
+                if condition.unsigned && otherwise.is_empty()
+        ));
+        assert_eq!(otherwise.len(), 1);
+    }
+
+    #[test]
+    fn loops_hold_blocks_too() {
+        let asm = CoreAsm::builder()
+            .i32("i", 0)
+            .for_loop("i", 0, 10, 2, |body| {
+                body.while_loop(Condition::new("i", Compare::Gt, 4), |body| {
+                    body.print(|p| p.var("i")).break_loop()
+                })
+                .continue_loop()
+            })
+            .build()
+            .unwrap();
+        let [Statement::For { step: 2, body, .. }] = asm.statements.as_slice() else {
+            panic!("expected a for loop");
+        };
+        assert!(matches!(
+            body.as_slice(),
+            [Statement::While { body, .. }, Statement::Continue]
+                if matches!(body.as_slice(), [Statement::Print(_), Statement::Break])
+        ));
+    }
+
+    #[test]
+    fn sums_run_until_the_condition_fails() {
+        let asm = CoreAsm::builder()
+            .i32("n", 0)
+            .i64("total", 0)
+            .set("n", 10)
+            .while_loop(Condition::new("n", Compare::Gt, 0), |body| {
+                body.add("total", "n").sub("n", 3)
+            })
+            .build()
+            .unwrap();
+        let [Statement::Set { var, value }, Statement::While { body, .. }] =
+            asm.statements.as_slice()
+        else {
+            panic!("expected a set and a while loop");
+        };
+        assert_eq!((var.as_str(), value), ("n", &Value::Const(10)));
+        assert!(matches!(
+            body.as_slice(),
+            [Statement::Add { var, .. }, Statement::Sub { .. }] if var == "total"
+        ));
+        // an i64 doesn't fit the i32 it would be added to
+        let built = CoreAsm::builder()
+            .i32("n", 0)
+            .i64("total", 0)
+            .add("n", "total")
+            .build();
+        assert!(matches!(
+            built,
+            Err(MakeError::TypeMismatch { var, .. }) if var == "total"
+        ));
+    }
+
+    #[test]
+    fn registers_and_keywords_are_plain_names() {
+        let asm = CoreAsm::builder()
+            .i64("rax", 1)
+            .i32("offset", 2)
+            .string("sp", "x")
+            .print(|p| p.var("rax").var("offset").var("sp"))
+            .if_then(Condition::new("rax", Compare::Lt, "offset"), |then| {
+                then.print(|p| p.nl())
+            })
+            .build()
+            .unwrap();
+        let x86 = Target::new(Bit::X64, Arch::X86, OS::Linux);
+        let text = maker(&asm, x86).unwrap();
+        assert!(text.contains("cv_rax dq 1") && text.contains("cv_offset dd 2"));
+        assert!(text.contains("mov rdi, cv_rax") && text.contains("cv_sp db \"x\""));
+        let arm = Target::new(Bit::X64, Arch::Arm, OS::Linux);
+        assert!(maker(&asm, arm).unwrap().contains("cv_sp:"));
+    }
+
+    #[test]
+    fn mistakes_surface_at_build() {
+        let error = |builder: Builder| builder.build().err().unwrap();
+        assert!(matches!(
+            error(CoreAsm::builder().declare("n", Types::I32, VarValue::I64(1))),
+            MakeError::TypeMismatch {
+                expected: Types::I32,
+                found: Types::I64,
+                ..
+            }
+        ));
+        assert!(matches!(
+            error(CoreAsm::builder().i32("9lives", 9)),
+            MakeError::BadName(name) if name == "9lives"
+        ));
+        assert!(matches!(
+            error(CoreAsm::builder().i32("n", 1).i64("n", 2)),
+            MakeError::DuplicateVariable(name) if name == "n"
+        ));
+        assert!(matches!(
+            error(CoreAsm::builder().print(|p| p.var("who"))),
+            MakeError::UndefinedVariable(name) if name == "who"
+        ));
+        assert!(matches!(
+            error(CoreAsm::builder().i32("n", 1).print(|p| p.float("n", 2))),
+            MakeError::TypeMismatch { .. }
+        ));
+        assert!(matches!(
+            error(CoreAsm::builder().f64("x", 1.0).if_then(
+                Condition::new("x", Compare::Eq, 1),
+                |then| then.print(|p| p.var("who"))
+            )),
+            MakeError::TypeMismatch { var, .. } if var == "x"
+        ));
+        assert!(matches!(
+            error(
+                CoreAsm::builder()
+                    .i32("i", 0)
+                    .for_loop("i", 0, 10, 1 << 40, |body| body)
+            ),
+            MakeError::BadStep { step, .. } if step == 1 << 40
+        ));
+        assert!(matches!(
+            error(
+                CoreAsm::builder()
+                    .i32("i", 0)
+                    .for_loop("i", 1i64 << 40, 0, -1, |body| body)
+            ),
+            MakeError::TypeMismatch { var, expected: Types::I32, .. } if var == "i"
+        ));
+        assert!(matches!(
+            error(CoreAsm::builder().if_then(
+                Condition::new(1, Compare::Eq, 1),
+                |then| then.continue_loop()
+            )),
+            MakeError::OutsideLoop(name) if name == "continue"
+        ));
+        assert!(matches!(
+            error(CoreAsm::builder().if_then(
+                Condition::new(1, Compare::Eq, 1),
+                |then| then.print(|p| p.var("who"))
+            )),
+            MakeError::UndefinedVariable(name) if name == "who"
+        ));
+    }
+}
@@ -1,25 +1,207 @@
-use std::collections::HashMap;
+use crate::{builder::Builder, error::MakeError, runtime::MAX_PRECISION};
 
+// with the serde feature a program can also be kept as JSON or TOML (see
+// load); fields left out take their defaults
+#[derive(Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct CoreAsm {
     pub data: Data,
+    // the older, print-only form of a program; one with anything else uses
+    // `statements` instead, and validate refuses a program with both
     pub prints: Vec<Print>,
+    // what the program does, in order
+    pub statements: Vec<Statement>,
     pub exit: bool,
+    // the status handed to exit, when `exit` is set
+    pub exit_code: u8,
 }
-#[allow(dead_code)]
-
 impl CoreAsm {
     pub fn exit(&mut self, exit: bool) {
         self.exit = exit;
     }
 
+    pub fn exit_with(&mut self, code: u8) {
+        self.exit = true;
+        self.exit_code = code;
+    }
+
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
     pub fn new() -> Self {
         CoreAsm {
             data: Data::new(),
             prints: Vec::new(),
+            statements: Vec::new(),
             exit: false,
+            exit_code: 0,
+        }
+    }
+
+    // what maker would otherwise trip over: names that can't be labels,
+    // variables declared twice or holding a value of another type, prints of
+    // variables that don't exist or can't take a precision, comparisons of
+    // anything but integers, loops that can't be lowered, and programs that
+    // fill both `prints` and `statements`, which couldn't run in the order
+    // they were written
+    pub fn validate(&self) -> Result<(), MakeError> {
+        if !self.prints.is_empty() && !self.statements.is_empty() {
+            return Err(MakeError::MixedPrints);
+        }
+        let variables = &self.data.variables;
+        for (i, (name, var)) in variables.iter().enumerate() {
+            if !is_valid_name(name) {
+                return Err(MakeError::BadName(name.clone()));
+            }
+            if variables[..i].iter().any(|(n, _)| n == name) {
+                return Err(MakeError::DuplicateVariable(name.clone()));
+            }
+            if var.value.types() != var.var_type {
+                return Err(MakeError::TypeMismatch {
+                    var: name.clone(),
+                    expected: var.var_type,
+                    found: var.value.types(),
+                });
+            }
+        }
+        for print in &self.prints {
+            self.check_print(print)?;
+        }
+        self.check_statements(&self.statements, false)
+    }
+
+    // the declared type of a variable
+    fn lookup(&self, name: &str) -> Result<Types, MakeError> {
+        self.data
+            .get(name)
+            .map(|var| var.var_type)
+            .ok_or_else(|| MakeError::UndefinedVariable(name.to_string()))
+    }
+
+    fn check_print(&self, print: &Print) -> Result<(), MakeError> {
+        for token in &print.tokens {
+            match token {
+                PrintToken::Variable(name) => {
+                    self.lookup(name)?;
+                }
+                PrintToken::Float { var, precision } => {
+                    let found = self.lookup(var)?;
+                    if !matches!(found, Types::F32 | Types::F64) {
+                        return Err(MakeError::TypeMismatch {
+                            var: var.clone(),
+                            expected: Types::F64,
+                            found,
+                        });
+                    }
+                    if *precision > MAX_PRECISION {
+                        return Err(MakeError::BadPrecision {
+                            var: var.clone(),
+                            precision: *precision,
+                        });
+                    }
+                }
+                PrintToken::Text(_) | PrintToken::Newline => {}
+            }
+        }
+        Ok(())
+    }
+
+    // the type of an integer variable, or None for a constant
+    fn integer(&self, value: &Value) -> Result<Option<Types>, MakeError> {
+        let Value::Var(name) = value else {
+            return Ok(None);
+        };
+        match self.lookup(name)? {
+            found @ (Types::I32 | Types::I64) => Ok(Some(found)),
+            found => Err(MakeError::TypeMismatch {
+                var: name.clone(),
+                expected: Types::I64,
+                found,
+            }),
         }
     }
 
+    // a value stored in, added to or taken from an integer variable. an I32
+    // one can't take an I64 variable or a constant outside 32 bits
+    fn check_fits(&self, var: &str, value: &Value) -> Result<(), MakeError> {
+        let narrow = self.integer(&Value::Var(var.to_string()))? == Some(Types::I32);
+        let fits = match value {
+            Value::Var(_) => self.integer(value)? == Some(Types::I32),
+            Value::Const(n) => i32::try_from(*n).is_ok(),
+        };
+        if narrow && !fits {
+            let name = match value {
+                Value::Var(name) => name,
+                Value::Const(_) => var,
+            };
+            return Err(MakeError::TypeMismatch {
+                var: name.to_string(),
+                expected: Types::I32,
+                found: Types::I64,
+            });
+        }
+        Ok(())
+    }
+
+    fn check_statements(&self, statements: &[Statement], in_loop: bool) -> Result<(), MakeError> {
+        for statement in statements {
+            match statement {
+                Statement::Print(print) => self.check_print(print)?,
+                Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                } => {
+                    self.integer(&condition.left)?;
+                    self.integer(&condition.right)?;
+                    self.check_statements(then, in_loop)?;
+                    self.check_statements(otherwise, in_loop)?;
+                }
+                Statement::While { condition, body } => {
+                    self.integer(&condition.left)?;
+                    self.integer(&condition.right)?;
+                    self.check_statements(body, true)?;
+                }
+                Statement::For {
+                    var,
+                    from,
+                    to,
+                    step,
+                    body,
+                } => {
+                    let narrow = self.integer(&Value::Var(var.clone()))? == Some(Types::I32);
+                    // the start is stored in the variable, so it has to fit,
+                    // and an end it can't hold would never be reached
+                    self.check_fits(var, from)?;
+                    self.check_fits(var, to)?;
+                    if *step == 0 || narrow && i32::try_from(*step).is_err() {
+                        return Err(MakeError::BadStep {
+                            var: var.clone(),
+                            step: *step,
+                        });
+                    }
+                    self.check_statements(body, true)?;
+                }
+                Statement::Set { var, value }
+                | Statement::Add { var, value }
+                | Statement::Sub { var, value } => self.check_fits(var, value)?,
+                Statement::Break if !in_loop => {
+                    return Err(MakeError::OutsideLoop("break".to_string()))
+                }
+                Statement::Continue if !in_loop => {
+                    return Err(MakeError::OutsideLoop("continue".to_string()))
+                }
+                Statement::Break | Statement::Continue => {}
+            }
+        }
+        Ok(())
+    }
+
     pub fn add_print(&mut self) -> &mut Print {
         let print = Print::new();
         self.prints.push(print);
@@ -38,6 +220,15 @@ impl CoreAsm {
         }
     }
 
+    pub fn add_float_to_last_print(&mut self, var_name: String, precision: u32) {
+        if let Some(print) = self.prints.last_mut() {
+            print.add_token(PrintToken::Float {
+                var: var_name,
+                precision,
+            });
+        }
+    }
+
     pub fn add_newline_to_last_print(&mut self) {
         if let Some(print) = self.prints.last_mut() {
             print.add_token(PrintToken::Newline);
@@ -45,20 +236,193 @@ impl CoreAsm {
     }
 }
 
+// variable names become labels, so they're ascii identifiers and stay clear
+// of the entry point and the labels maker makes up. registers and keywords
+// are fine, maker emits every variable under a `cv_` prefix
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name != "_start"
+        && !name.starts_with("__coreasm")
+}
+
+// variables are kept in declaration order so the data section comes out the
+// same on every run
+#[derive(Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Data {
-    pub variables: HashMap<String, Var>,
+    pub variables: Vec<(String, Var)>,
 }
 
+#[derive(Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Print {
     pub tokens: Vec<PrintToken>,
 }
 
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum PrintToken {
     Text(String),
     Variable(String),
+    // an F32/F64 variable with `precision` digits after the point
+    Float { var: String, precision: u32 },
     Newline,
 }
-#[allow(dead_code)]
+// something a program does, in the order given
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Statement {
+    Print(Print),
+    // runs `then` if the condition holds and `otherwise` if it doesn't
+    If {
+        condition: Condition,
+        then: Vec<Statement>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        otherwise: Vec<Statement>,
+    },
+    // runs `body` for as long as the condition holds, checking it first
+    While {
+        condition: Condition,
+        body: Vec<Statement>,
+    },
+    // sets `var` (an I32 or I64 variable) to `from` and runs `body` while
+    // it's below `to`, adding `step` after each run; a negative step counts
+    // down while it's above `to` instead. both bounds have to fit `var`. like `i < to; i += step` in C, the
+    // variable wraps if `to` is within a step of the type's limit
+    For {
+        var: String,
+        from: Value,
+        to: Value,
+        #[cfg_attr(feature = "serde", serde(default = "one"))]
+        step: i64,
+        body: Vec<Statement>,
+    },
+    // var = value, var += value and var -= value on an I32 or I64 variable.
+    // an I32 value is sign extended into an I64 variable, and the sums wrap
+    // around like the for step does
+    Set {
+        var: String,
+        value: Value,
+    },
+    Add {
+        var: String,
+        value: Value,
+    },
+    Sub {
+        var: String,
+        value: Value,
+    },
+    // leave the innermost loop
+    Break,
+    // go on to the innermost loop's next run, stepping a for loop first
+    Continue,
+}
+
+#[cfg(feature = "serde")]
+fn one() -> i64 {
+    1
+}
+
+// an integer comparison. the sides are compared as 64-bit if either is an
+// I64 variable or a constant that doesn't fit 32 bits, I32 variables being
+// sign extended; `unsigned` compares the bits as unsigned numbers
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Condition {
+    pub left: Value,
+    pub compare: Compare,
+    pub right: Value,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unsigned: bool,
+}
+
+impl Condition {
+    pub fn new(left: impl Into<Value>, compare: Compare, right: impl Into<Value>) -> Self {
+        Condition {
+            left: left.into(),
+            compare,
+            right: right.into(),
+            unsigned: false,
+        }
+    }
+
+    pub fn unsigned(self) -> Self {
+        Condition {
+            unsigned: true,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// one side of a Condition, or what a Set, Add or Sub uses: an I32 or I64
+// variable, or a constant
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Value {
+    Var(String),
+    Const(i64),
+}
+
+impl From<&str> for Value {
+    fn from(name: &str) -> Self {
+        Value::Var(name.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(name: String) -> Self {
+        Value::Var(name)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(n: i32) -> Self {
+        Value::Const(n.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Const(n)
+    }
+}
 
 impl Print {
     pub fn new() -> Self {
@@ -73,23 +437,40 @@ impl Print {
 impl Data {
     pub fn new() -> Self {
         Data {
-            variables: HashMap::new(),
+            variables: Vec::new(),
         }
     }
 
     pub fn mkvar(&mut self, name: String, var_type: Types, value: VarValue) {
         let var = Var { var_type, value };
-        self.variables.insert(name, var);
+        // redefining a variable replaces it but keeps its original position
+        match self.variables.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, existing)) => *existing = var,
+            None => self.variables.push((name, var)),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Var> {
+        self.variables
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, var)| var)
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Var {
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
     pub var_type: Types,
     pub value: VarValue,
 }
 
-#[allow(dead_code)]
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Types {
     I32,
     I64,
@@ -97,8 +478,11 @@ pub enum Types {
     F64,
     String,
 }
-#[allow(dead_code)]
-
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum VarValue {
     I32(i32),
     I64(i64),
@@ -107,12 +491,22 @@ pub enum VarValue {
     String(String),
 }
 
-#[allow(dead_code)]
+impl VarValue {
+    pub fn types(&self) -> Types {
+        match self {
+            VarValue::I32(_) => Types::I32,
+            VarValue::I64(_) => Types::I64,
+            VarValue::F32(_) => Types::F32,
+            VarValue::F64(_) => Types::F64,
+            VarValue::String(_) => Types::String,
+        }
+    }
+}
+
+#[derive(Default)]
 pub struct Entry {
     pub code: Vec<String>,
 }
-#[allow(dead_code)]
-
 impl Entry {
     pub fn new() -> Self {
         Entry { code: Vec::new() }
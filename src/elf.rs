@@ -0,0 +1,764 @@
+use crate::{
+    arch::{Arch, Bit, Target},
+    encode::{Object, Reloc, RelocKind, Section},
+    error::MakeError,
+};
+use std::{collections::HashMap, fs, io, path::Path};
+
+// where the image is loaded, the usual base for static executables
+const BASE: u64 = 0x400000;
+const PAGE: u64 = 0x1000;
+
+const ET_REL: u16 = 1;
+const ET_EXEC: u16 = 2;
+
+const EM_386: u16 = 3;
+const EM_ARM: u16 = 40;
+const EM_X86_64: u16 = 62;
+// version 5 of the arm eabi
+const EF_ARM_EABI_VER5: u32 = 0x05000000;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+const SHT_REL: u32 = 9;
+const SHF_WRITE: u64 = 1;
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_SECTION: u8 = 3;
+
+const R_X86_64_PC32: u32 = 2;
+const R_386_32: u32 = 1;
+const R_386_PC32: u32 = 2;
+const R_ARM_ABS32: u32 = 2;
+const R_ARM_CALL: u32 = 28;
+const R_ARM_JUMP24: u32 = 29;
+
+// what differs between targets: x86-64 gets ELF64, i386 and arm ELF32
+struct Format {
+    wide: bool,
+    machine: u16,
+    flags: u32,
+}
+
+impl Format {
+    fn new(target: &Target) -> Result<Self, MakeError> {
+        let (wide, machine, flags) = match (target.arch, target.bit) {
+            (Arch::X86, Bit::X64) => (true, EM_X86_64, 0),
+            (Arch::X86, Bit::X32) => (false, EM_386, 0),
+            (Arch::Arm, Bit::X32) => (false, EM_ARM, EF_ARM_EABI_VER5),
+            (Arch::Arm, Bit::X64) => return Err(MakeError::UnsupportedTarget(target.to_string())),
+        };
+        Ok(Format {
+            wide,
+            machine,
+            flags,
+        })
+    }
+
+    // addresses, offsets and sizes are this many bytes
+    fn word(&self) -> u64 {
+        if self.wide {
+            8
+        } else {
+            4
+        }
+    }
+
+    fn ehdr_size(&self) -> u64 {
+        if self.wide {
+            64
+        } else {
+            52
+        }
+    }
+
+    fn phdr_size(&self) -> u64 {
+        if self.wide {
+            56
+        } else {
+            32
+        }
+    }
+
+    fn shdr_size(&self) -> u64 {
+        if self.wide {
+            64
+        } else {
+            40
+        }
+    }
+
+    fn sym_size(&self) -> u64 {
+        if self.wide {
+            24
+        } else {
+            16
+        }
+    }
+
+    // x86-64 relocations carry their addend; the 32-bit abis keep it in the
+    // field being relocated
+    fn rel_size(&self) -> u64 {
+        if self.wide {
+            24
+        } else {
+            8
+        }
+    }
+
+    fn addr(&self, out: &mut Vec<u8>, value: u64) {
+        if self.wide {
+            out.extend_from_slice(&value.to_le_bytes());
+        } else {
+            out.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+    }
+
+    fn reloc_type(&self, kind: RelocKind) -> u32 {
+        match (self.machine, kind) {
+            (EM_X86_64, RelocKind::Pc32) => R_X86_64_PC32,
+            (EM_386, RelocKind::Pc32) => R_386_PC32,
+            (EM_386, RelocKind::Abs32) => R_386_32,
+            (EM_ARM, RelocKind::Abs32) => R_ARM_ABS32,
+            (EM_ARM, RelocKind::Call24) => R_ARM_CALL,
+            (EM_ARM, RelocKind::Jump24) => R_ARM_JUMP24,
+            _ => unreachable!("no {:?} relocation for machine {}", kind, self.machine),
+        }
+    }
+}
+
+fn align(n: u64, to: u64) -> u64 {
+    n.div_ceil(to) * to
+}
+
+// the fields of the ELF header that differ between file types
+fn header(
+    out: &mut Vec<u8>,
+    format: &Format,
+    kind: u16,
+    entry: u64,
+    phnum: u16,
+    shoff: u64,
+    shnum: u16,
+) {
+    // 32 or 64-bit, little endian, version 1, System V abi
+    out.extend_from_slice(b"\x7fELF");
+    out.push(if format.wide { 2 } else { 1 });
+    out.extend_from_slice(b"\x01\x01\x00");
+    out.resize(16, 0);
+    out.extend_from_slice(&kind.to_le_bytes());
+    out.extend_from_slice(&format.machine.to_le_bytes());
+    out.extend_from_slice(&1u32.to_le_bytes());
+    format.addr(out, entry);
+    let phoff = if phnum > 0 { format.ehdr_size() } else { 0 };
+    format.addr(out, phoff);
+    format.addr(out, shoff);
+    out.extend_from_slice(&format.flags.to_le_bytes());
+    out.extend_from_slice(&(format.ehdr_size() as u16).to_le_bytes());
+    out.extend_from_slice(&(format.phdr_size() as u16).to_le_bytes());
+    out.extend_from_slice(&phnum.to_le_bytes());
+    out.extend_from_slice(&(format.shdr_size() as u16).to_le_bytes());
+    out.extend_from_slice(&shnum.to_le_bytes());
+    // .shstrtab is always the last section
+    out.extend_from_slice(&shnum.saturating_sub(1).to_le_bytes());
+}
+
+// stores a relocation's value in the field it points at
+fn patch(text: &mut [u8], reloc: &Reloc, value: i64) {
+    let at = reloc.offset as usize;
+    let old = u32::from_le_bytes(text[at..at + 4].try_into().unwrap());
+    let new = match reloc.kind {
+        RelocKind::Pc32 | RelocKind::Abs32 => value as u32,
+        // a word offset below the condition and opcode bits
+        RelocKind::Call24 | RelocKind::Jump24 => {
+            old & 0xFF00_0000 | (value >> 2) as u32 & 0x00FF_FFFF
+        }
+    };
+    text[at..at + 4].copy_from_slice(&new.to_le_bytes());
+}
+
+// the text with every relocation patched for the given load addresses
+fn link(object: &Object, text_addr: u64, data_addr: u64) -> Result<Vec<u8>, MakeError> {
+    let addresses: HashMap<&str, u64> = object
+        .symbols
+        .iter()
+        .map(|s| {
+            let base = match s.section {
+                Section::Text => text_addr,
+                Section::Data => data_addr,
+            };
+            (s.name.as_str(), base + s.offset)
+        })
+        .collect();
+    let mut text = object.text.clone();
+    for reloc in &object.relocs {
+        let symbol = *addresses
+            .get(reloc.symbol.as_str())
+            .ok_or_else(|| MakeError::UndefinedSymbol(reloc.symbol.clone()))?;
+        let value = symbol as i64 + reloc.addend;
+        let value = match reloc.kind {
+            RelocKind::Abs32 => value,
+            _ => value - (text_addr + reloc.offset) as i64,
+        };
+        patch(&mut text, reloc, value);
+    }
+    Ok(text)
+}
+
+fn program_header(
+    out: &mut Vec<u8>,
+    format: &Format,
+    flags: u32,
+    offset: u64,
+    addr: u64,
+    size: u64,
+) {
+    out.extend_from_slice(&PT_LOAD.to_le_bytes());
+    // ELF64 moved the flags up next to the type
+    if format.wide {
+        out.extend_from_slice(&flags.to_le_bytes());
+    }
+    format.addr(out, offset);
+    // virtual and physical address
+    format.addr(out, addr);
+    format.addr(out, addr);
+    // size in the file and in memory
+    format.addr(out, size);
+    format.addr(out, size);
+    if !format.wide {
+        out.extend_from_slice(&flags.to_le_bytes());
+    }
+    format.addr(out, PAGE);
+}
+
+// a static executable: one read/execute segment holding the headers and the
+// text, one read/write segment for the data on the next page
+pub fn executable(object: &Object, target: &Target) -> Result<Vec<u8>, MakeError> {
+    let format = Format::new(target)?;
+    let text_off = align(format.ehdr_size() + 2 * format.phdr_size(), 16);
+    let data_off = align(text_off + object.text.len() as u64, PAGE);
+    let text = link(object, BASE + text_off, BASE + data_off)?;
+    let entry = object
+        .symbols
+        .iter()
+        .find(|s| s.name == object.entry && s.section == Section::Text)
+        .ok_or_else(|| MakeError::UndefinedSymbol(object.entry.clone()))?;
+
+    let mut out = Vec::new();
+    header(
+        &mut out,
+        &format,
+        ET_EXEC,
+        BASE + text_off + entry.offset,
+        2,
+        0,
+        0,
+    );
+
+    let text_end = text_off + text.len() as u64;
+    program_header(&mut out, &format, PF_R | PF_X, 0, BASE, text_end);
+    program_header(
+        &mut out,
+        &format,
+        PF_R | PF_W,
+        data_off,
+        BASE + data_off,
+        object.data.len() as u64,
+    );
+    out.resize(text_off as usize, 0);
+    out.extend_from_slice(&text);
+    out.resize(data_off as usize, 0);
+    out.extend_from_slice(&object.data);
+    Ok(out)
+}
+
+// a string table: names are offsets into it, and offset 0 is the empty name
+struct Strings(Vec<u8>);
+
+impl Strings {
+    fn new() -> Self {
+        Strings(vec![0])
+    }
+
+    fn add(&mut self, name: &str) -> u32 {
+        let at = self.0.len() as u32;
+        self.0.extend_from_slice(name.as_bytes());
+        self.0.push(0);
+        at
+    }
+}
+
+fn symbol(
+    out: &mut Vec<u8>,
+    format: &Format,
+    name: u32,
+    bind: u8,
+    kind: u8,
+    shndx: u16,
+    value: u64,
+) {
+    out.extend_from_slice(&name.to_le_bytes());
+    // ELF32 puts the value and size first, ELF64 last
+    if !format.wide {
+        format.addr(out, value);
+        format.addr(out, 0);
+    }
+    out.push(bind << 4 | kind);
+    out.push(0);
+    out.extend_from_slice(&shndx.to_le_bytes());
+    if format.wide {
+        format.addr(out, value);
+        format.addr(out, 0);
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entsize: u64,
+}
+
+impl SectionHeader {
+    fn write(&self, out: &mut Vec<u8>, format: &Format) {
+        out.extend_from_slice(&self.name.to_le_bytes());
+        out.extend_from_slice(&self.kind.to_le_bytes());
+        format.addr(out, self.flags);
+        // address, unset until linked
+        format.addr(out, 0);
+        format.addr(out, self.offset);
+        format.addr(out, self.size);
+        out.extend_from_slice(&self.link.to_le_bytes());
+        out.extend_from_slice(&self.info.to_le_bytes());
+        format.addr(out, self.align);
+        format.addr(out, self.entsize);
+    }
+}
+
+// section indices in a relocatable object; the relocations, .note.GNU-stack
+// and .shstrtab follow
+const TEXT: u16 = 1;
+const DATA: u16 = 2;
+const BSS: u16 = 3;
+const SYMTAB: u16 = 4;
+const STRTAB: u16 = 5;
+
+// a relocatable object for the system linker. every label is a local symbol
+// except the entry point; labels the program uses but doesn't define are
+// left undefined for other objects to provide
+pub fn relocatable(object: &Object, target: &Target) -> Result<Vec<u8>, MakeError> {
+    let format = Format::new(target)?;
+    let sym_size = format.sym_size() as usize;
+    let mut strtab = Strings::new();
+    let mut symtab = vec![0; sym_size];
+    for shndx in [TEXT, DATA, BSS] {
+        symbol(&mut symtab, &format, 0, STB_LOCAL, STT_SECTION, shndx, 0);
+    }
+    let (globals, locals): (Vec<_>, Vec<_>) =
+        object.symbols.iter().partition(|s| s.name == object.entry);
+    let first_global = 4 + locals.len();
+    let mut index = HashMap::new();
+    for (n, s) in locals.iter().chain(globals.iter()).enumerate() {
+        let bind = if s.name == object.entry {
+            STB_GLOBAL
+        } else {
+            STB_LOCAL
+        };
+        let shndx = match s.section {
+            Section::Text => TEXT,
+            Section::Data => DATA,
+        };
+        let name = strtab.add(&s.name);
+        symbol(
+            &mut symtab,
+            &format,
+            name,
+            bind,
+            STT_NOTYPE,
+            shndx,
+            s.offset,
+        );
+        index.insert(s.name.as_str(), 4 + n);
+    }
+    let mut text = object.text.clone();
+    let mut rel = Vec::new();
+    for reloc in &object.relocs {
+        let sym = match index.get(reloc.symbol.as_str()) {
+            Some(sym) => *sym,
+            None => {
+                let name = strtab.add(&reloc.symbol);
+                symbol(&mut symtab, &format, name, STB_GLOBAL, STT_NOTYPE, 0, 0);
+                let sym = symtab.len() / sym_size - 1;
+                index.insert(reloc.symbol.as_str(), sym);
+                sym
+            }
+        };
+        let kind = format.reloc_type(reloc.kind);
+        if format.wide {
+            rel.extend_from_slice(&reloc.offset.to_le_bytes());
+            rel.extend_from_slice(&((sym as u64) << 32 | kind as u64).to_le_bytes());
+            rel.extend_from_slice(&reloc.addend.to_le_bytes());
+        } else {
+            rel.extend_from_slice(&(reloc.offset as u32).to_le_bytes());
+            rel.extend_from_slice(&((sym as u32) << 8 | kind).to_le_bytes());
+            patch(&mut text, reloc, reloc.addend);
+        }
+    }
+
+    let (rel_name, rel_kind) = if format.wide {
+        (".rela.text", SHT_RELA)
+    } else {
+        (".rel.text", SHT_REL)
+    };
+    let mut shstrtab = Strings::new();
+    let names: Vec<u32> = [
+        ".text",
+        ".data",
+        ".bss",
+        ".symtab",
+        ".strtab",
+        rel_name,
+        ".note.GNU-stack",
+        ".shstrtab",
+    ]
+    .iter()
+    .map(|name| shstrtab.add(name))
+    .collect();
+
+    // contents follow the ELF header in section order, the headers come last
+    let word = format.word();
+    let mut body = vec![0; format.ehdr_size() as usize];
+    let place = |body: &mut Vec<u8>, bytes: &[u8], align_to: u64| {
+        body.resize(align(body.len() as u64, align_to) as usize, 0);
+        let offset = body.len() as u64;
+        body.extend_from_slice(bytes);
+        offset
+    };
+    let text_off = place(&mut body, &text, 16);
+    let data_off = place(&mut body, &object.data, 8);
+    let symtab_off = place(&mut body, &symtab, word);
+    let strtab_off = place(&mut body, &strtab.0, 1);
+    let rel_off = place(&mut body, &rel, word);
+    let shstrtab_off = place(&mut body, &shstrtab.0, 1);
+    let progbits = |name, flags, offset, size, align| SectionHeader {
+        name,
+        kind: SHT_PROGBITS,
+        flags,
+        offset,
+        size,
+        link: 0,
+        info: 0,
+        align,
+        entsize: 0,
+    };
+    let sections = [
+        SectionHeader {
+            kind: 0,
+            ..progbits(0, 0, 0, 0, 0)
+        },
+        progbits(
+            names[0],
+            SHF_ALLOC | SHF_EXECINSTR,
+            text_off,
+            text.len() as u64,
+            16,
+        ),
+        progbits(
+            names[1],
+            SHF_ALLOC | SHF_WRITE,
+            data_off,
+            object.data.len() as u64,
+            8,
+        ),
+        SectionHeader {
+            kind: SHT_NOBITS,
+            ..progbits(names[2], SHF_ALLOC | SHF_WRITE, data_off, 0, 1)
+        },
+        SectionHeader {
+            kind: SHT_SYMTAB,
+            link: STRTAB as u32,
+            info: first_global as u32,
+            entsize: format.sym_size(),
+            ..progbits(names[3], 0, symtab_off, symtab.len() as u64, word)
+        },
+        SectionHeader {
+            kind: SHT_STRTAB,
+            ..progbits(names[4], 0, strtab_off, strtab.0.len() as u64, 1)
+        },
+        SectionHeader {
+            kind: rel_kind,
+            link: SYMTAB as u32,
+            info: TEXT as u32,
+            entsize: format.rel_size(),
+            ..progbits(names[5], 0, rel_off, rel.len() as u64, word)
+        },
+        // empty, it tells the linker the stack needn't be executable
+        progbits(names[6], 0, shstrtab_off, 0, 1),
+        SectionHeader {
+            kind: SHT_STRTAB,
+            ..progbits(names[7], 0, shstrtab_off, shstrtab.0.len() as u64, 1)
+        },
+    ];
+    let shoff = align(body.len() as u64, word);
+    body.resize(shoff as usize, 0);
+    for section in &sections {
+        section.write(&mut body, &format);
+    }
+    let mut out = Vec::new();
+    header(
+        &mut out,
+        &format,
+        ET_REL,
+        0,
+        0,
+        shoff,
+        sections.len() as u16,
+    );
+    body[..out.len()].copy_from_slice(&out);
+    Ok(body)
+}
+
+// writes an image and marks it executable
+pub fn write(path: &Path, image: &[u8]) -> io::Result<()> {
+    fs::write(path, image)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::OS;
+    use crate::coreasm::{Compare, Condition, CoreAsm, Print, PrintToken, Types, VarValue};
+    use crate::maker;
+    use std::process::Command;
+
+    fn hello() -> CoreAsm {
+        let mut asm = CoreAsm::new();
+        asm.data.mkvar(
+            "name".to_string(),
+            Types::String,
+            VarValue::String("joy".to_string()),
+        );
+        asm.data
+            .mkvar("n".to_string(), Types::I32, VarValue::I32(-42));
+        asm.data
+            .mkvar("x".to_string(), Types::F64, VarValue::F64(2.5));
+        // the integers naive negation or a 32-bit formatter gets wrong
+        let edges = [
+            ("lo", VarValue::I64(i64::MIN)),
+            ("hi", VarValue::I64(i64::MAX)),
+            ("lo32", VarValue::I32(i32::MIN)),
+            ("zero", VarValue::I64(0)),
+        ];
+        let names = edges.each_ref().map(|(name, _)| *name);
+        for (name, value) in edges {
+            asm.data.mkvar(name.to_string(), value.types(), value);
+        }
+        let mut print = Print::new();
+        print.add_token(PrintToken::Text("Hello ".to_string()));
+        print.add_token(PrintToken::Variable("name".to_string()));
+        print.add_token(PrintToken::Text("!".to_string()));
+        print.add_token(PrintToken::Newline);
+        asm.prints.push(print);
+        let mut print = Print::new();
+        print.add_token(PrintToken::Variable("n".to_string()));
+        print.add_token(PrintToken::Text(" ".to_string()));
+        print.add_token(PrintToken::Float {
+            var: "x".to_string(),
+            precision: 2,
+        });
+        print.add_token(PrintToken::Newline);
+        asm.prints.push(print);
+        let mut print = Print::new();
+        for name in names {
+            print.add_token(PrintToken::Variable(name.to_string()));
+            print.add_token(PrintToken::Text(" ".to_string()));
+        }
+        print.add_token(PrintToken::Newline);
+        asm.prints.push(print);
+        asm.exit(true);
+        asm
+    }
+
+    // what hello prints
+    const OUTPUT: &str = "Hello joy!\n-42 2.50\n\
+        -9223372036854775808 9223372036854775807 -2147483648 0 \n";
+
+    // a while loop that ends through its own condition, and sums that carry
+    // and borrow between the words of an i64 on 32-bit targets
+    fn counter() -> CoreAsm {
+        CoreAsm::builder()
+            .i32("n", 0)
+            .i64("big", 4294967294)
+            .i32("m", -7)
+            .i64("w", 0)
+            .while_loop(Condition::new("n", Compare::Lt, 6), |body| {
+                body.print(|p| p.var("n").text(" ")).add("n", 2)
+            })
+            .add("big", "n")
+            .print(|p| p.var("big").text(" "))
+            .sub("big", 5)
+            .print(|p| p.var("big").text(" "))
+            .set("w", "m")
+            .sub("w", -10)
+            .print(|p| p.var("w").nl())
+            .exit(0)
+            .build()
+            .unwrap()
+    }
+
+    const COUNTED: &str = "0 2 4 4294967300 4294967295 3\n";
+
+    #[test]
+    fn headers_describe_the_image() {
+        let target = Target::new(Bit::X64, Arch::X86, OS::Linux);
+        let image = maker::executable(&hello(), target).unwrap();
+        assert_eq!(&image[..4], b"\x7fELF");
+        // e_type, e_machine
+        assert_eq!(&image[16..20], &[2, 0, 62, 0]);
+        // two PT_LOAD segments, text r-x and data rw-
+        let flags = |n: usize| image[64 + n * 56 + 4];
+        assert_eq!((flags(0), flags(1)), (5, 6));
+        let entry = u64::from_le_bytes(image[24..32].try_into().unwrap());
+        assert_eq!(entry, BASE + 0xB0);
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn executable_runs() {
+        let target = Target::new(Bit::X64, Arch::X86, OS::Linux);
+        for (asm, expected) in [(hello(), OUTPUT), (counter(), COUNTED)] {
+            let image = maker::executable(&asm, target).unwrap();
+            let path = std::env::temp_dir().join(format!("coreasm-elf-{}", std::process::id()));
+            write(&path, &image).unwrap();
+            let output = Command::new(&path).output().unwrap();
+            fs::remove_file(&path).unwrap();
+            assert!(output.status.success());
+            assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+        }
+    }
+
+    // kernels built without ia32 emulation can't start it, so it only runs
+    // when asked for (cargo test -- --ignored)
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    #[ignore = "needs ia32 emulation"]
+    fn i386_executable_runs() {
+        let target = Target::new(Bit::X32, Arch::X86, OS::Linux);
+        for (asm, expected) in [(hello(), OUTPUT), (counter(), COUNTED)] {
+            let image = maker::executable(&asm, target).unwrap();
+            assert_eq!(&image[16..20], &[2, 0, 3, 0]);
+            let path = std::env::temp_dir().join(format!("coreasm-elf32-{}", std::process::id()));
+            write(&path, &image).unwrap();
+            let output = Command::new(&path).output();
+            fs::remove_file(&path).unwrap();
+            let output = output.expect("the kernel can't run i386 executables");
+            assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+        }
+    }
+
+    #[test]
+    fn arm_images_are_elf32() {
+        let target = || Target::new(Bit::X32, Arch::Arm, OS::Linux);
+        let word =
+            |image: &[u8], at: usize| u32::from_le_bytes(image[at..at + 4].try_into().unwrap());
+        let image = maker::executable(&hello(), target()).unwrap();
+        // class, e_type, e_machine, e_flags
+        assert_eq!(image[4], 1);
+        assert_eq!(&image[16..20], &[2, 0, 40, 0]);
+        assert_eq!(word(&image, 36), EF_ARM_EABI_VER5);
+        // the text follows a 52 byte header and two 32 byte program headers
+        assert_eq!(word(&image, 24) as u64, BASE + 0x80);
+        let object = maker::object(&hello(), target()).unwrap();
+        assert_eq!(&object[16..20], &[1, 0, 40, 0]);
+        // e_shentsize
+        assert_eq!(&object[46..48], &[40, 0]);
+    }
+
+    #[test]
+    fn other_targets_are_rejected() {
+        let target = Target::new(Bit::X64, Arch::Arm, OS::Linux);
+        assert!(matches!(
+            maker::executable(&hello(), target),
+            Err(MakeError::UnsupportedTarget(_))
+        ));
+    }
+
+    // the section names of an ELF64 image
+    fn section_names(image: &[u8]) -> Vec<String> {
+        let word = |at: usize| u64::from_le_bytes(image[at..at + 8].try_into().unwrap()) as usize;
+        let half = |at: usize| u16::from_le_bytes(image[at..at + 2].try_into().unwrap()) as usize;
+        let shoff = word(40);
+        let header = |n: usize| shoff + n * 64;
+        let names = word(header(half(62)) + 24);
+        (0..half(60))
+            .map(|n| {
+                let name = &image[names + half(header(n))..];
+                let end = name.iter().position(|b| *b == 0).unwrap();
+                String::from_utf8_lossy(&name[..end]).into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn object_has_the_expected_sections() {
+        let target = Target::new(Bit::X64, Arch::X86, OS::Linux);
+        let image = maker::object(&hello(), target).unwrap();
+        assert_eq!(&image[16..20], &[1, 0, 62, 0]);
+        assert_eq!(
+            section_names(&image),
+            [
+                "",
+                ".text",
+                ".data",
+                ".bss",
+                ".symtab",
+                ".strtab",
+                ".rela.text",
+                ".note.GNU-stack",
+                ".shstrtab"
+            ]
+        );
+    }
+
+    // links with the system linker, so it only runs when asked for
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    #[ignore = "needs ld"]
+    fn object_links_with_ld() {
+        let target = Target::new(Bit::X64, Arch::X86, OS::Linux);
+        let image = maker::object(&hello(), target).unwrap();
+        let dir = std::env::temp_dir();
+        let obj = dir.join(format!("coreasm-obj-{}.o", std::process::id()));
+        let exe = dir.join(format!("coreasm-obj-{}", std::process::id()));
+        fs::write(&obj, &image).unwrap();
+        let linked = Command::new("ld").arg("-o").arg(&exe).arg(&obj).status();
+        fs::remove_file(&obj).unwrap();
+        assert!(linked.expect("ld isn't installed").success());
+        let output = Command::new(&exe).output().unwrap();
+        fs::remove_file(&exe).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), OUTPUT);
+    }
+}
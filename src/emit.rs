@@ -0,0 +1,736 @@
+use crate::{
+    arch::{A64Reg, Arch, ArmReg, Bit, Register, Target, Trap, Width},
+    ir::{Cond, DataValue, Instr, Mem, Operand, Program},
+};
+
+fn operand(op: &Operand, word: Width) -> String {
+    match op {
+        Operand::Reg(r) => r.name(word).to_string(),
+        Operand::Imm(i) => i.to_string(),
+        Operand::Label(l) => l.clone(),
+    }
+}
+
+fn x86_mem(mem: &Mem, word: Width) -> String {
+    match mem.offset {
+        0 => format!("[{}]", mem.base.name(word)),
+        o if o < 0 => format!("[{} - {}]", mem.base.name(word), -(o as i64)),
+        o => format!("[{} + {}]", mem.base.name(word), o),
+    }
+}
+
+fn x86_cond(cond: Cond) -> &'static str {
+    match cond {
+        Cond::Eq => "e",
+        Cond::Ne => "ne",
+        Cond::Lt => "l",
+        Cond::Le => "le",
+        Cond::Gt => "g",
+        Cond::Ge => "ge",
+        Cond::Below => "b",
+        Cond::BelowEq => "be",
+        Cond::Above => "a",
+        Cond::AboveEq => "ae",
+        Cond::Unordered => "p",
+    }
+}
+
+// the size keyword on a memory operand; gas wants `ptr` after it
+fn x86_size(width: Width, gas: bool) -> String {
+    let size = match width {
+        Width::W8 => "byte",
+        Width::W32 => "dword",
+        Width::W64 => "qword",
+    };
+    if gas {
+        format!("{} ptr", size)
+    } else {
+        size.to_string()
+    }
+}
+
+// movsd or movss for a float load or store
+fn x86_float_mov(width: Width) -> &'static str {
+    match width {
+        Width::W64 => "movsd",
+        _ => "movss",
+    }
+}
+
+// intel syntax, as nasm or gas with .intel_syntax noprefix want it. the two
+// differ in size keywords and in gas reading a bare label as a memory operand
+fn x86(instr: &Instr, word: Width, gas: bool) -> String {
+    let size = |width| x86_size(width, gas);
+    match instr {
+        Instr::Label(l) => format!("{}:", l),
+        Instr::Mov {
+            dst,
+            src: Operand::Label(l),
+        } if gas => format!("mov {}, offset {}", dst.name(word), l),
+        Instr::Mov { dst, src } => format!("mov {}, {}", dst.name(word), operand(src, word)),
+        Instr::Load { dst, src, width } if dst.is_float() => format!(
+            "{} {}, {} {}",
+            x86_float_mov(*width),
+            dst.name(word),
+            size(*width),
+            x86_mem(src, word)
+        ),
+        Instr::Store { dst, src, width } if src.is_float() => format!(
+            "{} {} {}, {}",
+            x86_float_mov(*width),
+            size(*width),
+            x86_mem(dst, word),
+            src.name(word)
+        ),
+        Instr::Load { dst, src, width } => match (width, word) {
+            (Width::W8, _) => format!(
+                "movzx {}, {} {}",
+                dst.name(Width::W32),
+                size(Width::W8),
+                x86_mem(src, word)
+            ),
+            (Width::W32, Width::W64) => format!(
+                "movsxd {}, {} {}",
+                dst.name(word),
+                size(Width::W32),
+                x86_mem(src, word)
+            ),
+            _ => format!(
+                "mov {}, {} {}",
+                dst.name(word),
+                size(*width),
+                x86_mem(src, word)
+            ),
+        },
+        Instr::Store { dst, src, width } => format!(
+            "mov {} {}, {}",
+            size(*width),
+            x86_mem(dst, word),
+            src.name(*width)
+        ),
+        Instr::Add { dst, src } => format!("add {}, {}", dst.name(word), operand(src, word)),
+        Instr::Sub { dst, src } => format!("sub {}, {}", dst.name(word), operand(src, word)),
+        Instr::Neg(r) => format!("neg {}", r.name(word)),
+        Instr::FAdd { dst, src } => format!("addsd {}, {}", dst.name(word), src.name(word)),
+        Instr::FSub { dst, src } => format!("subsd {}, {}", dst.name(word), src.name(word)),
+        Instr::FMul { dst, src } => format!("mulsd {}, {}", dst.name(word), src.name(word)),
+        Instr::FDiv { dst, src } => format!("divsd {}, {}", dst.name(word), src.name(word)),
+        Instr::FMov { dst, src } => format!("movapd {}, {}", dst.name(word), src.name(word)),
+        Instr::FCmp { lhs, rhs } => format!("ucomisd {}, {}", lhs.name(word), rhs.name(word)),
+        Instr::FToInt { dst, src } => {
+            format!("cvttsd2si {}, {}", dst.name(word), src.name(word))
+        }
+        Instr::IntToF { dst, src } => format!("cvtsi2sd {}, {}", dst.name(word), src.name(word)),
+        Instr::FWiden(r) => format!("cvtss2sd {}, {}", r.name(word), r.name(word)),
+        Instr::Cmp { lhs, rhs } => format!("cmp {}, {}", lhs.name(word), operand(rhs, word)),
+        Instr::Jump(l) => format!("jmp {}", l),
+        Instr::JumpIf { cond, target } => format!("j{} {}", x86_cond(*cond), target),
+        Instr::Call(l) => format!("call {}", l),
+        Instr::Ret => "ret".to_string(),
+        Instr::Push(r) => format!("push {}", r.name(word)),
+        Instr::Pop(r) => format!("pop {}", r.name(word)),
+        Instr::Syscall(trap) => trap.to_string(),
+    }
+}
+
+fn att_reg(r: &Register, width: Width) -> String {
+    format!("%{}", r.name(width))
+}
+
+fn att_operand(op: &Operand, word: Width) -> String {
+    match op {
+        Operand::Reg(r) => att_reg(r, word),
+        Operand::Imm(i) => format!("${}", i),
+        Operand::Label(l) => format!("${}", l),
+    }
+}
+
+fn att_mem(mem: &Mem, word: Width) -> String {
+    match mem.offset {
+        0 => format!("({})", att_reg(&mem.base, word)),
+        o => format!("{}({})", o, att_reg(&mem.base, word)),
+    }
+}
+
+fn att_suffix(width: Width) -> char {
+    match width {
+        Width::W8 => 'b',
+        Width::W32 => 'l',
+        Width::W64 => 'q',
+    }
+}
+
+// at&t syntax for gas: source first, % on registers, $ on immediates and
+// label addresses, and the operand size as a mnemonic suffix
+fn att(instr: &Instr, word: Width) -> String {
+    let q = att_suffix(word);
+    let r = |r: &Register| att_reg(r, word);
+    match instr {
+        Instr::Label(l) => format!("{}:", l),
+        Instr::Mov { dst, src } => format!("mov{} {}, {}", q, att_operand(src, word), r(dst)),
+        Instr::Load { dst, src, width } if dst.is_float() => format!(
+            "{} {}, {}",
+            x86_float_mov(*width),
+            att_mem(src, word),
+            r(dst)
+        ),
+        Instr::Store { dst, src, width } if src.is_float() => format!(
+            "{} {}, {}",
+            x86_float_mov(*width),
+            r(src),
+            att_mem(dst, word)
+        ),
+        Instr::Load { dst, src, width } => match (width, word) {
+            (Width::W8, _) => format!(
+                "movzbl {}, {}",
+                att_mem(src, word),
+                att_reg(dst, Width::W32)
+            ),
+            (Width::W32, Width::W64) => format!("movslq {}, {}", att_mem(src, word), r(dst)),
+            _ => format!("mov{} {}, {}", q, att_mem(src, word), r(dst)),
+        },
+        Instr::Store { dst, src, width } => format!(
+            "mov{} {}, {}",
+            att_suffix(*width),
+            att_reg(src, *width),
+            att_mem(dst, word)
+        ),
+        Instr::Add { dst, src } => format!("add{} {}, {}", q, att_operand(src, word), r(dst)),
+        Instr::Sub { dst, src } => format!("sub{} {}, {}", q, att_operand(src, word), r(dst)),
+        Instr::Neg(dst) => format!("neg{} {}", q, r(dst)),
+        Instr::FAdd { dst, src } => format!("addsd {}, {}", r(src), r(dst)),
+        Instr::FSub { dst, src } => format!("subsd {}, {}", r(src), r(dst)),
+        Instr::FMul { dst, src } => format!("mulsd {}, {}", r(src), r(dst)),
+        Instr::FDiv { dst, src } => format!("divsd {}, {}", r(src), r(dst)),
+        Instr::FMov { dst, src } => format!("movapd {}, {}", r(src), r(dst)),
+        // `ucomisd a, b` in intel compares a against b, so the operands swap too
+        Instr::FCmp { lhs, rhs } => format!("ucomisd {}, {}", r(rhs), r(lhs)),
+        Instr::FToInt { dst, src } => format!("cvttsd2si{} {}, {}", q, r(src), r(dst)),
+        Instr::IntToF { dst, src } => format!("cvtsi2sd{} {}, {}", q, r(src), r(dst)),
+        Instr::FWiden(x) => format!("cvtss2sd {}, {}", r(x), r(x)),
+        Instr::Cmp { lhs, rhs } => format!("cmp{} {}, {}", q, att_operand(rhs, word), r(lhs)),
+        Instr::Jump(l) => format!("jmp {}", l),
+        Instr::JumpIf { cond, target } => format!("j{} {}", x86_cond(*cond), target),
+        Instr::Call(l) => format!("call {}", l),
+        Instr::Ret => "ret".to_string(),
+        Instr::Push(x) => format!("push{} {}", q, r(x)),
+        Instr::Pop(x) => format!("pop{} {}", q, r(x)),
+        Instr::Syscall(Trap::Int80) => "int $0x80".to_string(),
+        Instr::Syscall(trap) => trap.to_string(),
+    }
+}
+
+fn arm_mem(mem: &Mem, word: Width) -> String {
+    match mem.offset {
+        0 => format!("[{}]", mem.base.name(word)),
+        o => format!("[{}, #{}]", mem.base.name(word), o),
+    }
+}
+
+fn arm_cond(cond: Cond) -> &'static str {
+    match cond {
+        Cond::Eq => "eq",
+        Cond::Ne => "ne",
+        Cond::Lt => "lt",
+        Cond::Le => "le",
+        Cond::Gt => "gt",
+        Cond::Ge => "ge",
+        Cond::Below => "lo",
+        Cond::BelowEq => "ls",
+        Cond::Above => "hi",
+        Cond::AboveEq => "hs",
+        Cond::Unordered => "vs",
+    }
+}
+
+fn arm_operand(op: &Operand, word: Width) -> String {
+    match op {
+        Operand::Imm(i) => format!("#{}", i),
+        _ => operand(op, word),
+    }
+}
+
+// x16 and ip are the intra-procedure-call scratch registers. nothing in the
+// ir uses them, so immediates that don't encode are loaded there first
+fn arm_scratch(bit: Bit) -> Register {
+    match bit {
+        Bit::X64 => Register::A64(A64Reg::X16),
+        Bit::X32 => Register::Arm(ArmReg::R12),
+    }
+}
+
+// add, sub and cmp take 12 bits on aarch64 and a rotated byte on arm
+fn arm_imm_fits(i: i64, bit: Bit) -> bool {
+    match bit {
+        Bit::X64 => (0..4096).contains(&i),
+        Bit::X32 => {
+            (0..=u32::MAX as i64).contains(&i)
+                && (0..16).any(|r| (i as u32).rotate_left(2 * r) <= 0xFF)
+        }
+    }
+}
+
+// `op dst, dst, src`, going through the scratch register for big immediates
+fn arm_arith(op: &str, dst: &Register, src: &Operand, bit: Bit, word: Width) -> String {
+    match src {
+        Operand::Imm(i) if !arm_imm_fits(*i, bit) => {
+            let scratch = arm_scratch(bit).name(word);
+            format!(
+                "ldr {}, ={}\n{} {}, {}, {}",
+                scratch,
+                i,
+                op,
+                dst.name(word),
+                dst.name(word),
+                scratch
+            )
+        }
+        _ => format!(
+            "{} {}, {}, {}",
+            op,
+            dst.name(word),
+            dst.name(word),
+            arm_operand(src, word)
+        ),
+    }
+}
+
+// aarch64 and 32-bit arm share most mnemonics; the differences are returns,
+// conditional branches and the stack
+fn arm(instr: &Instr, bit: Bit) -> String {
+    let word = match bit {
+        Bit::X64 => Width::W64,
+        Bit::X32 => Width::W32,
+    };
+    // 32-bit arm spells the vfp instructions differently and has to go through
+    // a single precision register to move between core and vfp registers; s15
+    // (the top of d7) is kept free for that
+    let vfp = bit == Bit::X32;
+    let f = |r: &Register| r.name(Width::W64);
+    match instr {
+        Instr::Label(l) => format!("{}:", l),
+        // addresses and immediates too big for a mov come from the literal pool
+        Instr::Mov {
+            dst,
+            src: Operand::Label(l),
+        } => format!("ldr {}, ={}", dst.name(word), l),
+        Instr::Mov {
+            dst,
+            src: Operand::Imm(i),
+        } if !(0..256).contains(i) => format!("ldr {}, ={}", dst.name(word), i),
+        Instr::Mov { dst, src } => format!("mov {}, {}", dst.name(word), arm_operand(src, word)),
+        Instr::Load { dst, src, width } if dst.is_float() => format!(
+            "{} {}, {}",
+            if vfp { "vldr" } else { "ldr" },
+            dst.name(*width),
+            arm_mem(src, word)
+        ),
+        Instr::Store { dst, src, width } if src.is_float() => format!(
+            "{} {}, {}",
+            if vfp { "vstr" } else { "str" },
+            src.name(*width),
+            arm_mem(dst, word)
+        ),
+        Instr::Load { dst, src, width } => match (width, bit) {
+            (Width::W8, _) => format!("ldrb {}, {}", dst.name(Width::W32), arm_mem(src, word)),
+            (Width::W32, Bit::X64) => {
+                format!("ldrsw {}, {}", dst.name(word), arm_mem(src, word))
+            }
+            _ => format!("ldr {}, {}", dst.name(word), arm_mem(src, word)),
+        },
+        Instr::Store { dst, src, width } => match width {
+            Width::W8 => format!("strb {}, {}", src.name(Width::W32), arm_mem(dst, word)),
+            _ => format!("str {}, {}", src.name(*width), arm_mem(dst, word)),
+        },
+        Instr::Add { dst, src } => arm_arith("add", dst, src, bit, word),
+        Instr::Sub { dst, src } => arm_arith("sub", dst, src, bit, word),
+        Instr::Neg(r) => match bit {
+            Bit::X64 => format!("neg {}, {}", r.name(word), r.name(word)),
+            Bit::X32 => format!("rsb {}, {}, #0", r.name(word), r.name(word)),
+        },
+        Instr::FAdd { dst, src } if vfp => format!("vadd.f64 {}, {}, {}", f(dst), f(dst), f(src)),
+        Instr::FAdd { dst, src } => format!("fadd {}, {}, {}", f(dst), f(dst), f(src)),
+        Instr::FSub { dst, src } if vfp => format!("vsub.f64 {}, {}, {}", f(dst), f(dst), f(src)),
+        Instr::FSub { dst, src } => format!("fsub {}, {}, {}", f(dst), f(dst), f(src)),
+        Instr::FMul { dst, src } if vfp => format!("vmul.f64 {}, {}, {}", f(dst), f(dst), f(src)),
+        Instr::FMul { dst, src } => format!("fmul {}, {}, {}", f(dst), f(dst), f(src)),
+        Instr::FDiv { dst, src } if vfp => format!("vdiv.f64 {}, {}, {}", f(dst), f(dst), f(src)),
+        Instr::FDiv { dst, src } => format!("fdiv {}, {}, {}", f(dst), f(dst), f(src)),
+        Instr::FMov { dst, src } if vfp => format!("vmov.f64 {}, {}", f(dst), f(src)),
+        Instr::FMov { dst, src } => format!("fmov {}, {}", f(dst), f(src)),
+        Instr::FCmp { lhs, rhs } if vfp => {
+            format!("vcmp.f64 {}, {}\nvmrs APSR_nzcv, fpscr", f(lhs), f(rhs))
+        }
+        Instr::FCmp { lhs, rhs } => format!("fcmp {}, {}", f(lhs), f(rhs)),
+        Instr::FToInt { dst, src } if vfp => {
+            format!("vcvt.s32.f64 s15, {}\nvmov {}, s15", f(src), dst.name(word))
+        }
+        Instr::FToInt { dst, src } => format!("fcvtzs {}, {}", dst.name(word), f(src)),
+        Instr::IntToF { dst, src } if vfp => {
+            format!("vmov s15, {}\nvcvt.f64.s32 {}, s15", src.name(word), f(dst))
+        }
+        Instr::IntToF { dst, src } => format!("scvtf {}, {}", f(dst), src.name(word)),
+        Instr::FWiden(r) if vfp => format!("vcvt.f64.f32 {}, {}", f(r), r.name(Width::W32)),
+        Instr::FWiden(r) => format!("fcvt {}, {}", f(r), r.name(Width::W32)),
+        Instr::Cmp {
+            lhs,
+            rhs: Operand::Imm(i),
+        } if !arm_imm_fits(*i, bit) => format!(
+            "ldr {}, ={}\ncmp {}, {}",
+            arm_scratch(bit).name(word),
+            i,
+            lhs.name(word),
+            arm_scratch(bit).name(word)
+        ),
+        Instr::Cmp { lhs, rhs } => format!("cmp {}, {}", lhs.name(word), arm_operand(rhs, word)),
+        Instr::Jump(l) => format!("b {}", l),
+        Instr::JumpIf { cond, target } => match bit {
+            Bit::X64 => format!("b.{} {}", arm_cond(*cond), target),
+            Bit::X32 => format!("b{} {}", arm_cond(*cond), target),
+        },
+        Instr::Call(l) => format!("bl {}", l),
+        Instr::Ret => match bit {
+            Bit::X64 => "ret".to_string(),
+            Bit::X32 => "bx lr".to_string(),
+        },
+        // aarch64 keeps sp 16 byte aligned, so each push takes a full slot
+        Instr::Push(r) => match bit {
+            Bit::X64 => format!("str {}, [sp, #-16]!", r.name(word)),
+            Bit::X32 => format!("push {{{}}}", r.name(word)),
+        },
+        Instr::Pop(r) => match bit {
+            Bit::X64 => format!("ldr {}, [sp], #16", r.name(word)),
+            Bit::X32 => format!("pop {{{}}}", r.name(word)),
+        },
+        Instr::Syscall(trap) => trap.to_string(),
+    }
+}
+
+// how the output is spelled. nasm is intel syntax only; the two gas syntaxes
+// only differ on x86. arm has no at&t syntax, just gnu as's own, so there
+// GasAtt stands for "gnu as's default syntax" (and GasIntel means the same)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Nasm,
+    GasIntel,
+    GasAtt,
+}
+
+impl Syntax {
+    // nasm for x86, gas's own syntax (spelled GasAtt) for arm
+    pub fn default_for(target: &Target) -> Syntax {
+        match target.arch {
+            Arch::X86 => Syntax::Nasm,
+            Arch::Arm => Syntax::GasAtt,
+        }
+    }
+}
+
+pub fn instr(instr: &Instr, target: &Target, syntax: Syntax) -> String {
+    match (target.arch, syntax) {
+        (Arch::X86, Syntax::GasAtt) => att(instr, target.word()),
+        (Arch::X86, _) => x86(instr, target.word(), syntax == Syntax::GasIntel),
+        (Arch::Arm, _) => arm(instr, target.bit),
+    }
+}
+
+// a db operand list: runs of plain printable ascii go in quotes, everything
+// else (quotes, backslashes, control characters, utf-8 bytes) as numbers,
+// e.g. "abc", 0x0A, 0x22
+fn nasm_bytes(bytes: &[u8]) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    let mut run = String::new();
+    for b in bytes {
+        match b {
+            b' '..=b'~' if *b != b'"' && *b != b'\\' => run.push(*b as char),
+            _ => {
+                if !run.is_empty() {
+                    parts.push(format!("\"{}\"", run));
+                    run.clear();
+                }
+                parts.push(format!("0x{:02X}", b));
+            }
+        }
+    }
+    if !run.is_empty() {
+        parts.push(format!("\"{}\"", run));
+    }
+    parts.join(", ")
+}
+
+fn nasm_data(value: &DataValue) -> String {
+    match value {
+        DataValue::Bytes(b) => format!("db {}", nasm_bytes(b)),
+        DataValue::I32(i) => format!("dd {}", i),
+        DataValue::I64(i) => format!("dq {}", i),
+        // raw ieee bits, so every value (1.0, inf, nan) assembles as a float
+        DataValue::F32(f) => format!("dd 0x{:08X}", f.to_bits()),
+        DataValue::F64(f) => format!("dq 0x{:016X}", f.to_bits()),
+        DataValue::Table(t) => format!(
+            "dq {}",
+            t.iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+pub fn nasm(program: &Program, target: &Target) -> String {
+    let mut asm_code = String::new();
+    asm_code.push_str("SECTION .data\n");
+    for item in &program.data {
+        if item.value.align() > 1 {
+            asm_code.push_str(&format!("     align {}\n", item.value.align()));
+        }
+        match &item.value {
+            // nothing to define, just the label
+            DataValue::Bytes(b) if b.is_empty() => {
+                asm_code.push_str(&format!("     {}:\n", item.label))
+            }
+            value => asm_code.push_str(&format!("     {} {}\n", item.label, nasm_data(value))),
+        }
+    }
+    asm_code.push_str(&format!(
+        "SECTION .text\n      global {}\n{}:\n",
+        program.entry, program.entry
+    ));
+    for i in &program.text {
+        match i {
+            Instr::Label(l) => asm_code.push_str(&format!("{}:\n", l)),
+            _ => {
+                for line in instr(i, target, Syntax::Nasm).lines() {
+                    asm_code.push_str(&format!("     {}\n", line));
+                }
+            }
+        }
+    }
+    asm_code
+}
+
+// a .ascii string: printable ascii as is, everything else as an octal escape
+fn gas_bytes(bytes: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for b in bytes {
+        match b {
+            b' '..=b'~' if *b != b'"' && *b != b'\\' => out.push(*b as char),
+            _ => out.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn gas_data(value: &DataValue) -> String {
+    match value {
+        DataValue::Bytes(b) => format!(".ascii {}", gas_bytes(b)),
+        DataValue::I32(i) => format!(".4byte {}", i),
+        DataValue::I64(i) => format!(".8byte {}", i),
+        DataValue::F32(f) => format!(".4byte 0x{:08X}", f.to_bits()),
+        DataValue::F64(f) => format!(".8byte 0x{:016X}", f.to_bits()),
+        DataValue::Table(t) => format!(
+            ".8byte {}",
+            t.iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+// straight-line instructions between literal pools on 32-bit arm, whose
+// pc-relative loads only reach 4KB
+const ARM_POOL_LINES: usize = 256;
+
+// gnu as output: arm, or x86 in either intel or at&t syntax
+pub fn gas(program: &Program, target: &Target, syntax: Syntax) -> String {
+    let arm32 = target.arch == Arch::Arm && target.bit == Bit::X32;
+    let mut asm_code = String::new();
+    if target.arch == Arch::X86 && syntax == Syntax::GasIntel {
+        asm_code.push_str("     .intel_syntax noprefix\n");
+    }
+    if arm32 {
+        asm_code.push_str("     .syntax unified\n     .fpu vfp\n");
+    }
+    asm_code.push_str("     .data\n");
+    for item in &program.data {
+        if item.value.align() > 1 {
+            asm_code.push_str(&format!("     .balign {}\n", item.value.align()));
+        }
+        asm_code.push_str(&format!("{}:\n", item.label));
+        match &item.value {
+            DataValue::Bytes(b) if b.is_empty() => {}
+            value => asm_code.push_str(&format!("     {}\n", gas_data(value))),
+        }
+    }
+    asm_code.push_str(&format!(
+        "     .text\n     .globl {}\n{}:\n",
+        program.entry, program.entry
+    ));
+    let mut lines = 0;
+    let mut pool = 0;
+    for i in &program.text {
+        match i {
+            Instr::Label(l) => asm_code.push_str(&format!("{}:\n", l)),
+            _ => {
+                for line in instr(i, target, syntax).lines() {
+                    asm_code.push_str(&format!("     {}\n", line));
+                    lines += 1;
+                }
+            }
+        }
+        if !arm32 {
+            continue;
+        }
+        // nothing falls through a return, so its pool costs no branch
+        if *i == Instr::Ret {
+            asm_code.push_str("     .ltorg\n");
+            lines = 0;
+        } else if lines >= ARM_POOL_LINES {
+            let skip = format!("__coreasm_pool_{}", pool);
+            pool += 1;
+            asm_code.push_str(&format!("     b {}\n     .ltorg\n{}:\n", skip, skip));
+            lines = 0;
+        }
+    }
+    asm_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_ascii_stays_quoted() {
+        assert_eq!(nasm_bytes(b"Hello, world!"), "\"Hello, world!\"");
+    }
+
+    #[test]
+    fn quotes_and_backslashes_become_numbers() {
+        assert_eq!(
+            nasm_bytes(br#"say "hi" \o/"#),
+            "\"say \", 0x22, \"hi\", 0x22, \" \", 0x5C, \"o/\""
+        );
+    }
+
+    #[test]
+    fn control_characters_become_numbers() {
+        assert_eq!(
+            nasm_bytes(b"a\tb\r\n\0"),
+            "\"a\", 0x09, \"b\", 0x0D, 0x0A, 0x00"
+        );
+        assert_eq!(nasm_bytes(&[0x7F]), "0x7F");
+    }
+
+    #[test]
+    fn utf8_is_emitted_byte_by_byte() {
+        assert_eq!(
+            nasm_bytes("héllo ✓".as_bytes()),
+            "\"h\", 0xC3, 0xA9, \"llo \", 0xE2, 0x9C, 0x93"
+        );
+    }
+
+    #[test]
+    fn empty_strings_define_only_the_label() {
+        let mut program = Program::new("_start");
+        program.data("empty", DataValue::Bytes(Vec::new()));
+        let target = Target::new(Bit::X64, Arch::X86, crate::arch::OS::Linux);
+        assert!(nasm(&program, &target).contains("     empty:\n"));
+    }
+
+    #[test]
+    fn gas_strings_escape_in_octal() {
+        assert_eq!(
+            gas_bytes("a\"b\\\n✓".as_bytes()),
+            "\"a\\042b\\134\\012\\342\\234\\223\""
+        );
+    }
+
+    #[test]
+    fn arm_immediates_that_dont_encode_use_the_pool() {
+        let r0 = Register::Arm(ArmReg::R0);
+        let x0 = Register::A64(A64Reg::X0);
+        let add = |dst, i| Instr::Add {
+            dst,
+            src: Operand::Imm(i),
+        };
+        assert_eq!(
+            arm(&add(r0, 0xFF000000), Bit::X32),
+            "add r0, r0, #4278190080"
+        );
+        assert_eq!(
+            arm(&add(r0, 257), Bit::X32),
+            "ldr r12, =257\nadd r0, r0, r12"
+        );
+        assert_eq!(arm(&add(x0, 4095), Bit::X64), "add x0, x0, #4095");
+        assert_eq!(arm(&add(x0, -1), Bit::X64), "ldr x16, =-1\nadd x0, x0, x16");
+        let mov = |dst, src| Instr::Mov { dst, src };
+        assert_eq!(
+            arm(&mov(x0, Operand::Imm(1_000_000_000)), Bit::X64),
+            "ldr x0, =1000000000"
+        );
+        assert_eq!(
+            arm(&mov(r0, Operand::Label("msg".to_string())), Bit::X32),
+            "ldr r0, =msg"
+        );
+    }
+
+    #[test]
+    fn x86_syntaxes_agree_on_operands() {
+        use crate::arch::X86Reg;
+        let rax = Register::X86(X86Reg::Rax);
+        let rdx = Register::X86(X86Reg::Rdx);
+        let cases = [
+            (
+                Instr::Mov {
+                    dst: rax,
+                    src: Operand::Label("msg".to_string()),
+                },
+                "mov rax, msg",
+                "mov rax, offset msg",
+                "movq $msg, %rax",
+            ),
+            (
+                Instr::Store {
+                    dst: Mem {
+                        base: rdx,
+                        offset: -8,
+                    },
+                    src: rax,
+                    width: Width::W8,
+                },
+                "mov byte [rdx - 8], al",
+                "mov byte ptr [rdx - 8], al",
+                "movb %al, -8(%rdx)",
+            ),
+            (
+                Instr::Load {
+                    dst: rax,
+                    src: Mem {
+                        base: rdx,
+                        offset: 4,
+                    },
+                    width: Width::W32,
+                },
+                "movsxd rax, dword [rdx + 4]",
+                "movsxd rax, dword ptr [rdx + 4]",
+                "movslq 4(%rdx), %rax",
+            ),
+            (
+                Instr::Cmp {
+                    lhs: rax,
+                    rhs: Operand::Imm(-1),
+                },
+                "cmp rax, -1",
+                "cmp rax, -1",
+                "cmpq $-1, %rax",
+            ),
+        ];
+        for (i, nasm, intel, at_t) in cases {
+            assert_eq!(x86(&i, Width::W64, false), nasm);
+            assert_eq!(x86(&i, Width::W64, true), intel);
+            assert_eq!(att(&i, Width::W64), at_t);
+        }
+        assert_eq!(att(&Instr::Syscall(Trap::Int80), Width::W32), "int $0x80");
+    }
+}
@@ -0,0 +1,492 @@
+use super::{Reloc, RelocKind, Section, Symbol};
+use crate::{
+    arch::{ArmReg, Register, Target, Trap, Width},
+    error::MakeError,
+    ir::{Cond, Instr, Mem, Operand, Program},
+};
+use std::collections::HashMap;
+
+// every instruction is unconditional unless it is a branch
+const AL: u32 = 0xE << 28;
+// the immediate form of a data processing instruction
+const IMM: u32 = 1 << 25;
+// load/store offsets count up from the base
+const UP: u32 = 1 << 23;
+
+// data processing opcodes with the S bit, bits 20 to 27
+const SUB: u32 = 0x04;
+const RSB: u32 = 0x06;
+const ADD: u32 = 0x08;
+const CMP: u32 = 0x15;
+const MOV: u32 = 0x1A;
+const MVN: u32 = 0x1E;
+
+// ldr pc relative reaches 4095 bytes ahead; a pool is placed before the
+// oldest pending literal gets further away than this
+const POOL_RANGE: usize = 4000;
+
+// hardware register number; the vfp doubles count from 0 again
+fn num(r: &Register) -> u32 {
+    match r {
+        Register::Arm(r) => *r as u32 & 15,
+        _ => unreachable!("arm encoder given {:?}", r),
+    }
+}
+
+fn cc(cond: Cond) -> u32 {
+    (match cond {
+        Cond::Eq => 0x0,
+        Cond::Ne => 0x1,
+        Cond::AboveEq => 0x2,
+        Cond::Below => 0x3,
+        Cond::Unordered => 0x6,
+        Cond::Above => 0x8,
+        Cond::BelowEq => 0x9,
+        Cond::Ge => 0xA,
+        Cond::Lt => 0xB,
+        Cond::Gt => 0xC,
+        Cond::Le => 0xD,
+    }) << 28
+}
+
+// a byte rotated right by an even amount, the only immediates data
+// processing instructions take
+fn rotated(i: i64) -> Option<u32> {
+    let i = u32::try_from(i).ok()?;
+    (0..16)
+        .find(|r| i.rotate_left(2 * r) <= 0xFF)
+        .map(|r| r << 8 | i.rotate_left(2 * r))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Imm(u32),
+    Label(String),
+}
+
+struct Encoder<'a> {
+    target: &'a Target,
+    code: Vec<u8>,
+    labels: HashMap<String, usize>,
+    // b and bl fields to patch once every text label is known
+    fixups: Vec<(usize, String, RelocKind)>,
+    relocs: Vec<Reloc>,
+    // ldr instructions waiting for their literal to be placed
+    pool: Vec<(usize, Literal)>,
+}
+
+impl Encoder<'_> {
+    fn word(&mut self, word: u32) {
+        self.code.extend_from_slice(&word.to_le_bytes());
+    }
+
+    fn unencodable(&self, instr: &Instr) -> MakeError {
+        MakeError::Unencodable {
+            target: self.target.to_string(),
+            instr: format!("{:?}", instr),
+        }
+    }
+
+    // `op rd, rn, operand`, where operand is a register or IMM with an
+    // encoded immediate
+    fn dp(&mut self, op: u32, rd: u32, rn: u32, operand: u32) {
+        self.word(AL | op << 20 | rn << 16 | rd << 12 | operand);
+    }
+
+    // ldr rd, =literal
+    fn literal(&mut self, rd: u32, literal: Literal) {
+        self.pool.push((self.code.len(), literal));
+        self.word(AL | 0x059F0000 | rd << 12);
+    }
+
+    fn mov_imm(&mut self, instr: &Instr, rd: u32, i: i64) -> Result<(), MakeError> {
+        let imm = u32::try_from(i)
+            .or_else(|_| i32::try_from(i).map(|d| d as u32))
+            .map_err(|_| self.unencodable(instr))?;
+        if let Some(op2) = rotated(imm as i64) {
+            self.dp(MOV, rd, 0, IMM | op2);
+        } else if let Some(op2) = rotated(!imm as i64) {
+            self.dp(MVN, rd, 0, IMM | op2);
+        } else {
+            self.literal(rd, Literal::Imm(imm));
+        }
+        Ok(())
+    }
+
+    // add, sub and cmp; immediates that don't encode go through ip, the
+    // scratch register the gas output uses too
+    fn arith(
+        &mut self,
+        instr: &Instr,
+        op: u32,
+        rd: u32,
+        rn: u32,
+        src: &Operand,
+    ) -> Result<(), MakeError> {
+        match src {
+            Operand::Reg(r) => self.dp(op, rd, rn, num(r)),
+            Operand::Imm(i) => match rotated(*i) {
+                Some(imm) => self.dp(op, rd, rn, IMM | imm),
+                None => {
+                    let ip = ArmReg::R12 as u32;
+                    self.mov_imm(instr, ip, *i)?;
+                    self.dp(op, rd, rn, ip);
+                }
+            },
+            Operand::Label(_) => return Err(self.unencodable(instr)),
+        }
+        Ok(())
+    }
+
+    // ldr, str, ldrb and strb with a 12-bit offset
+    fn mem(&mut self, instr: &Instr, op: u32, rd: u32, mem: &Mem) -> Result<(), MakeError> {
+        let offset = mem.offset.unsigned_abs();
+        if offset > 0xFFF {
+            return Err(self.unencodable(instr));
+        }
+        let up = if mem.offset >= 0 { UP } else { 0 };
+        self.word(AL | op | up | num(&mem.base) << 16 | rd << 12 | offset);
+        Ok(())
+    }
+
+    // vldr and vstr take a word offset up to 255
+    fn vfp_mem(&mut self, instr: &Instr, op: u32, dd: u32, mem: &Mem) -> Result<(), MakeError> {
+        let offset = mem.offset.unsigned_abs();
+        if !offset.is_multiple_of(4) || offset / 4 > 0xFF {
+            return Err(self.unencodable(instr));
+        }
+        let up = if mem.offset >= 0 { UP } else { 0 };
+        self.word(AL | op | up | num(&mem.base) << 16 | dd << 12 | (offset / 4));
+        Ok(())
+    }
+
+    fn branch(&mut self, op: u32, label: &str, kind: RelocKind) {
+        self.fixups.push((self.code.len(), label.to_string(), kind));
+        self.word(op);
+    }
+
+    // places the pending literals here, one word each, shared when equal
+    fn flush(&mut self) {
+        let mut placed: Vec<(Literal, usize)> = Vec::new();
+        for (at, literal) in std::mem::take(&mut self.pool) {
+            let to = match placed.iter().find(|(l, _)| *l == literal) {
+                Some((_, to)) => *to,
+                None => {
+                    let to = self.code.len();
+                    match &literal {
+                        Literal::Imm(i) => self.word(*i),
+                        Literal::Label(l) => {
+                            self.relocs.push(Reloc {
+                                offset: to as u64,
+                                symbol: l.clone(),
+                                kind: RelocKind::Abs32,
+                                addend: 0,
+                            });
+                            self.word(0);
+                        }
+                    }
+                    placed.push((literal, to));
+                    to
+                }
+            };
+            // pc reads two instructions ahead, so a literal right after its
+            // ldr is behind it
+            let offset = to as i64 - (at as i64 + 8);
+            let mut ldr = u32::from_le_bytes(self.code[at..at + 4].try_into().unwrap());
+            if offset < 0 {
+                ldr &= !UP;
+            }
+            ldr |= offset.unsigned_abs() as u32;
+            self.code[at..at + 4].copy_from_slice(&ldr.to_le_bytes());
+        }
+    }
+
+    // a pool in the middle of the text, with a branch around it
+    fn flush_if_far(&mut self) {
+        let Some((first, _)) = self.pool.first() else {
+            return;
+        };
+        if self.code.len() + 4 * self.pool.len() - first < POOL_RANGE {
+            return;
+        }
+        let at = self.code.len();
+        self.word(0);
+        self.flush();
+        let offset = (self.code.len() - (at + 8)) as u32 >> 2;
+        self.code[at..at + 4].copy_from_slice(&(AL | 0x0A000000 | offset).to_le_bytes());
+    }
+
+    fn instr(&mut self, instr: &Instr) -> Result<(), MakeError> {
+        // s15, the top of d7, moves values between core and vfp registers
+        const S15: u32 = 7 << 12 | 1 << 22;
+        match instr {
+            Instr::Label(l) => {
+                self.labels.insert(l.clone(), self.code.len());
+            }
+            Instr::Mov { dst, src } => match src {
+                Operand::Reg(r) => self.dp(MOV, num(dst), 0, num(r)),
+                Operand::Imm(i) => self.mov_imm(instr, num(dst), *i)?,
+                Operand::Label(l) => self.literal(num(dst), Literal::Label(l.clone())),
+            },
+            Instr::Load { dst, src, width } if dst.is_float() => {
+                let op = if *width == Width::W64 {
+                    0x0D100B00
+                } else {
+                    0x0D100A00
+                };
+                self.vfp_mem(instr, op, num(dst), src)?
+            }
+            Instr::Store { dst, src, width } if src.is_float() => {
+                let op = if *width == Width::W64 {
+                    0x0D000B00
+                } else {
+                    0x0D000A00
+                };
+                self.vfp_mem(instr, op, num(src), dst)?
+            }
+            Instr::Load { dst, src, width } => match width {
+                Width::W8 => self.mem(instr, 0x05500000, num(dst), src)?,
+                _ => self.mem(instr, 0x05100000, num(dst), src)?,
+            },
+            Instr::Store { dst, src, width } => match width {
+                Width::W8 => self.mem(instr, 0x05400000, num(src), dst)?,
+                _ => self.mem(instr, 0x05000000, num(src), dst)?,
+            },
+            Instr::Add { dst, src } => self.arith(instr, ADD, num(dst), num(dst), src)?,
+            Instr::Sub { dst, src } => self.arith(instr, SUB, num(dst), num(dst), src)?,
+            Instr::Cmp { lhs, rhs } => self.arith(instr, CMP, 0, num(lhs), rhs)?,
+            // rsb r, r, #0
+            Instr::Neg(r) => self.dp(RSB, num(r), num(r), IMM),
+            Instr::FAdd { dst, src } => self.vfp(0x0E300B00, dst, dst, src),
+            Instr::FSub { dst, src } => self.vfp(0x0E300B40, dst, dst, src),
+            Instr::FMul { dst, src } => self.vfp(0x0E200B00, dst, dst, src),
+            Instr::FDiv { dst, src } => self.vfp(0x0E800B00, dst, dst, src),
+            Instr::FMov { dst, src } => self.word(AL | 0x0EB00B40 | num(dst) << 12 | num(src)),
+            // vcmp.f64, then vmrs APSR_nzcv, fpscr
+            Instr::FCmp { lhs, rhs } => {
+                self.word(AL | 0x0EB40B40 | num(lhs) << 12 | num(rhs));
+                self.word(AL | 0x0EF1FA10);
+            }
+            // vcvt.s32.f64 s15, d; vmov r, s15
+            Instr::FToInt { dst, src } => {
+                self.word(AL | 0x0EBD0BC0 | S15 | num(src));
+                self.word(AL | 0x0E170A90 | num(dst) << 12);
+            }
+            // vmov s15, r; vcvt.f64.s32 d, s15
+            Instr::IntToF { dst, src } => {
+                self.word(AL | 0x0E070A90 | num(src) << 12);
+                self.word(AL | 0x0EB80BE7 | num(dst) << 12);
+            }
+            // vcvt.f64.f32 dn, s2n
+            Instr::FWiden(r) => self.word(AL | 0x0EB70AC0 | num(r) << 12 | num(r)),
+            Instr::Jump(l) => self.branch(AL | 0x0A000000, l, RelocKind::Jump24),
+            Instr::JumpIf { cond, target } => {
+                self.branch(cc(*cond) | 0x0A000000, target, RelocKind::Jump24)
+            }
+            Instr::Call(l) => self.branch(AL | 0x0B000000, l, RelocKind::Call24),
+            // bx lr
+            Instr::Ret => self.word(AL | 0x012FFF1E),
+            // push and pop of one register are str rd, [sp, #-4]! and
+            // ldr rd, [sp], #4
+            Instr::Push(r) => self.word(AL | 0x052D0004 | num(r) << 12),
+            Instr::Pop(r) => self.word(AL | 0x049D0004 | num(r) << 12),
+            Instr::Syscall(Trap::Svc) => self.word(AL | 0x0F000000),
+            Instr::Syscall(_) => return Err(self.unencodable(instr)),
+        }
+        Ok(())
+    }
+
+    // double precision `op dd, dn, dm`
+    fn vfp(&mut self, op: u32, dd: &Register, dn: &Register, dm: &Register) {
+        self.word(AL | op | num(dn) << 16 | num(dd) << 12 | num(dm));
+    }
+}
+
+// 32-bit arm machine code for the program's text. label addresses and
+// immediates that don't fit an instruction are loaded from literal pools,
+// placed after each return and wherever the next one would be out of reach
+pub fn encode(
+    program: &Program,
+    target: &Target,
+    symbols: &mut Vec<Symbol>,
+) -> Result<(Vec<u8>, Vec<Reloc>), MakeError> {
+    let mut enc = Encoder {
+        target,
+        code: Vec::new(),
+        labels: HashMap::new(),
+        fixups: Vec::new(),
+        relocs: Vec::new(),
+        pool: Vec::new(),
+    };
+    enc.labels.insert(program.entry.clone(), 0);
+    symbols.push(Symbol {
+        name: program.entry.clone(),
+        section: Section::Text,
+        offset: 0,
+    });
+    for instr in &program.text {
+        enc.flush_if_far();
+        if let Instr::Label(l) = instr {
+            symbols.push(Symbol {
+                name: l.clone(),
+                section: Section::Text,
+                offset: enc.code.len() as u64,
+            });
+        }
+        enc.instr(instr)?;
+        if *instr == Instr::Ret {
+            enc.flush();
+        }
+    }
+    enc.flush();
+    for (at, label, kind) in std::mem::take(&mut enc.fixups) {
+        match enc.labels.get(&label) {
+            Some(to) => {
+                let rel = (*to as i64 - (at as i64 + 8)) >> 2;
+                let word = u32::from_le_bytes(enc.code[at..at + 4].try_into().unwrap());
+                let word = word | rel as u32 & 0xFF_FFFF;
+                enc.code[at..at + 4].copy_from_slice(&word.to_le_bytes());
+            }
+            None => enc.relocs.push(Reloc {
+                offset: at as u64,
+                symbol: label,
+                kind,
+                addend: -8,
+            }),
+        }
+    }
+    Ok((enc.code, enc.relocs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{Arch, Bit, OS};
+
+    fn reg(r: ArmReg) -> Register {
+        Register::Arm(r)
+    }
+
+    fn assemble(text: Vec<Instr>) -> (Vec<u8>, Vec<Reloc>) {
+        let mut program = Program::new("_start");
+        for instr in text {
+            program.push(instr);
+        }
+        let target = Target::new(Bit::X32, Arch::Arm, OS::Linux);
+        encode(&program, &target, &mut Vec::new()).unwrap()
+    }
+
+    // expected bytes are what llvm-mc produces for the gas output
+    #[test]
+    fn matches_llvm_mc() {
+        let mem = |base, offset| Mem {
+            base: reg(base),
+            offset,
+        };
+        let cases: Vec<(Instr, &[u8])> = vec![
+            (
+                Instr::Mov {
+                    dst: reg(ArmReg::R7),
+                    src: Operand::Imm(4),
+                },
+                &[0x04, 0x70, 0xA0, 0xE3],
+            ),
+            (
+                Instr::Mov {
+                    dst: reg(ArmReg::R0),
+                    src: Operand::Imm(-1),
+                },
+                &[0x00, 0x00, 0xE0, 0xE3],
+            ),
+            (
+                Instr::Add {
+                    dst: reg(ArmReg::Sp),
+                    src: Operand::Imm(336),
+                },
+                &[0x15, 0xDE, 0x8D, 0xE2],
+            ),
+            (
+                Instr::Load {
+                    dst: reg(ArmReg::R3),
+                    src: mem(ArmReg::R1, -1),
+                    width: Width::W8,
+                },
+                &[0x01, 0x30, 0x51, 0xE5],
+            ),
+            (
+                Instr::Store {
+                    dst: mem(ArmReg::Sp, 4),
+                    src: reg(ArmReg::D0),
+                    width: Width::W32,
+                },
+                &[0x01, 0x0A, 0x8D, 0xED],
+            ),
+            (
+                Instr::FCmp {
+                    lhs: reg(ArmReg::D0),
+                    rhs: reg(ArmReg::D1),
+                },
+                &[0x41, 0x0B, 0xB4, 0xEE, 0x10, 0xFA, 0xF1, 0xEE],
+            ),
+            (
+                Instr::FToInt {
+                    dst: reg(ArmReg::R3),
+                    src: reg(ArmReg::D2),
+                },
+                &[0xC2, 0x7B, 0xFD, 0xEE, 0x90, 0x3A, 0x17, 0xEE],
+            ),
+            (
+                Instr::IntToF {
+                    dst: reg(ArmReg::D1),
+                    src: reg(ArmReg::R1),
+                },
+                &[0x90, 0x1A, 0x07, 0xEE, 0xE7, 0x1B, 0xB8, 0xEE],
+            ),
+            (Instr::FWiden(reg(ArmReg::D1)), &[0xC1, 0x1A, 0xB7, 0xEE]),
+            (Instr::Neg(reg(ArmReg::R4)), &[0x00, 0x40, 0x64, 0xE2]),
+            (Instr::Push(reg(ArmReg::Lr)), &[0x04, 0xE0, 0x2D, 0xE5]),
+            (Instr::Pop(reg(ArmReg::R4)), &[0x04, 0x40, 0x9D, 0xE4]),
+        ];
+        for (instr, expected) in cases {
+            assert_eq!(assemble(vec![instr.clone()]).0, expected, "{:?}", instr);
+        }
+    }
+
+    #[test]
+    fn literals_go_to_a_pool_after_ret() {
+        let (code, relocs) = assemble(vec![
+            Instr::Mov {
+                dst: reg(ArmReg::R1),
+                src: Operand::Label("msg".to_string()),
+            },
+            Instr::Mov {
+                dst: reg(ArmReg::R2),
+                src: Operand::Label("msg".to_string()),
+            },
+            Instr::Cmp {
+                lhs: reg(ArmReg::R0),
+                rhs: Operand::Imm(1_000_001),
+            },
+            Instr::Ret,
+        ]);
+        let words: Vec<u32> = code
+            .chunks(4)
+            .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+            .collect();
+        // ldr r1, [pc, #12]; ldr r2, [pc, #8]; ldr ip, [pc, #8]; cmp r0, ip;
+        // bx lr; then msg once and the immediate
+        assert_eq!(
+            words,
+            [0xE59F100C, 0xE59F2008, 0xE59FC008, 0xE150000C, 0xE12FFF1E, 0, 1_000_001]
+        );
+        assert_eq!(
+            relocs,
+            [Reloc {
+                offset: 20,
+                symbol: "msg".to_string(),
+                kind: RelocKind::Abs32,
+                addend: 0,
+            }]
+        );
+    }
+}
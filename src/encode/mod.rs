@@ -0,0 +1,86 @@
+use crate::{
+    arch::{Arch, Bit, Target},
+    error::MakeError,
+    ir::Program,
+};
+
+mod arm;
+mod x86;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Text,
+    Data,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub section: Section,
+    pub offset: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocKind {
+    // 32-bit pc relative: symbol + addend - place
+    Pc32,
+    // 32-bit absolute: symbol + addend
+    Abs32,
+    // the 24-bit word offset of an arm bl or b: symbol + addend - place
+    Call24,
+    Jump24,
+}
+
+// a field in the text section that needs the final address of a symbol
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reloc {
+    pub offset: u64,
+    pub symbol: String,
+    pub kind: RelocKind,
+    pub addend: i64,
+}
+
+// machine code and data for a lowered program, before linking. text
+// references to labels outside the text are left as relocations
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub entry: String,
+    pub text: Vec<u8>,
+    pub data: Vec<u8>,
+    pub symbols: Vec<Symbol>,
+    pub relocs: Vec<Reloc>,
+}
+
+// lays the data items out back to back at their natural alignment
+fn data(program: &Program, symbols: &mut Vec<Symbol>) -> Vec<u8> {
+    let mut data = Vec::new();
+    for item in &program.data {
+        while data.len() % item.value.align() != 0 {
+            data.push(0);
+        }
+        symbols.push(Symbol {
+            name: item.label.clone(),
+            section: Section::Data,
+            offset: data.len() as u64,
+        });
+        data.extend(item.value.to_bytes());
+    }
+    data
+}
+
+pub fn encode(program: &Program, target: &Target) -> Result<Object, MakeError> {
+    let mut symbols = Vec::new();
+    let data = data(program, &mut symbols);
+    let (text, relocs) = match (target.arch, target.bit) {
+        (Arch::X86, _) => x86::encode(program, target, &mut symbols)?,
+        (Arch::Arm, Bit::X32) => arm::encode(program, target, &mut symbols)?,
+        (Arch::Arm, Bit::X64) => return Err(MakeError::UnsupportedTarget(target.to_string())),
+    };
+    Ok(Object {
+        entry: program.entry.clone(),
+        text,
+        data,
+        symbols,
+        relocs,
+    })
+}
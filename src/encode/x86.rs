@@ -0,0 +1,515 @@
+use super::{Reloc, RelocKind, Section, Symbol};
+use crate::{
+    arch::{Bit, Register, Target, Trap, Width},
+    error::MakeError,
+    ir::{Cond, Instr, Mem, Operand, Program},
+};
+use std::collections::HashMap;
+
+// hardware register number; X86Reg is in encoding order and the xmm
+// registers count from 0 again
+fn num(r: &Register) -> u8 {
+    match r {
+        Register::X86(r) => *r as u8 & 15,
+        _ => unreachable!("x86 encoder given {:?}", r),
+    }
+}
+
+fn cc(cond: Cond) -> u8 {
+    match cond {
+        Cond::Below => 0x2,
+        Cond::AboveEq => 0x3,
+        Cond::Eq => 0x4,
+        Cond::Ne => 0x5,
+        Cond::BelowEq => 0x6,
+        Cond::Above => 0x7,
+        Cond::Unordered => 0xA,
+        Cond::Lt => 0xC,
+        Cond::Ge => 0xD,
+        Cond::Le => 0xE,
+        Cond::Gt => 0xF,
+    }
+}
+
+struct Encoder<'a> {
+    target: &'a Target,
+    // 64-bit operands and rex prefixes; i386 has neither
+    wide: bool,
+    code: Vec<u8>,
+    labels: HashMap<String, usize>,
+    // rel32 fields to patch once every text label is known
+    fixups: Vec<(usize, String)>,
+    relocs: Vec<Reloc>,
+}
+
+impl Encoder<'_> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    // rex carries the 64-bit operand size and the high bit of both register
+    // fields; `force` is for byte access to spl, bpl, sil and dil
+    fn rex(&mut self, w: bool, reg: u8, rm: u8, force: bool) {
+        let rex = 0x40 | (w as u8) << 3 | (reg >> 3) << 2 | rm >> 3;
+        if rex != 0x40 || force {
+            self.code.push(rex);
+        }
+    }
+
+    // op reg, rm with both operands in registers
+    fn reg_reg(&mut self, prefix: Option<u8>, w: bool, opcode: &[u8], reg: u8, rm: u8) {
+        if let Some(p) = prefix {
+            self.code.push(p);
+        }
+        self.rex(w, reg, rm, false);
+        self.bytes(opcode);
+        self.code.push(0xC0 | (reg & 7) << 3 | rm & 7);
+    }
+
+    // op reg, [base + offset]
+    fn reg_mem(
+        &mut self,
+        prefix: Option<u8>,
+        w: bool,
+        opcode: &[u8],
+        reg: u8,
+        mem: &Mem,
+        byte_reg: bool,
+    ) {
+        if let Some(p) = prefix {
+            self.code.push(p);
+        }
+        let base = num(&mem.base);
+        self.rex(w, reg, base, byte_reg && (4..8).contains(&reg));
+        self.bytes(opcode);
+        // rbp and r13 have no form without a displacement
+        let mode = match mem.offset {
+            0 if base & 7 != 5 => 0,
+            o if i8::try_from(o).is_ok() => 1,
+            _ => 2,
+        };
+        self.code.push(mode << 6 | (reg & 7) << 3 | base & 7);
+        // rsp and r12 as a base need a sib byte
+        if base & 7 == 4 {
+            self.code.push(0x24);
+        }
+        match mode {
+            1 => self.code.push(mem.offset as i8 as u8),
+            2 => self.bytes(&mem.offset.to_le_bytes()),
+            _ => {}
+        }
+    }
+
+    fn rel32(&mut self, label: &str) {
+        self.fixups.push((self.code.len(), label.to_string()));
+        self.bytes(&[0; 4]);
+    }
+
+    fn unencodable(&self, instr: &Instr) -> MakeError {
+        MakeError::Unencodable {
+            target: self.target.to_string(),
+            instr: format!("{:?}", instr),
+        }
+    }
+
+    // add, sub and cmp: `ext` picks the operation in the immediate forms,
+    // `opcode` is the r/m, reg form
+    fn alu(
+        &mut self,
+        instr: &Instr,
+        ext: u8,
+        opcode: u8,
+        dst: &Register,
+        src: &Operand,
+    ) -> Result<(), MakeError> {
+        match src {
+            Operand::Reg(r) => self.reg_reg(None, self.wide, &[opcode], num(r), num(dst)),
+            Operand::Imm(i) => {
+                if let Ok(b) = i8::try_from(*i) {
+                    self.reg_reg(None, self.wide, &[0x83], ext, num(dst));
+                    self.code.push(b as u8);
+                } else if let Ok(d) = i32::try_from(*i) {
+                    self.reg_reg(None, self.wide, &[0x81], ext, num(dst));
+                    self.bytes(&d.to_le_bytes());
+                } else {
+                    return Err(self.unencodable(instr));
+                }
+            }
+            Operand::Label(_) => return Err(self.unencodable(instr)),
+        }
+        Ok(())
+    }
+
+    fn instr(&mut self, instr: &Instr) -> Result<(), MakeError> {
+        match instr {
+            Instr::Label(l) => {
+                self.labels.insert(l.clone(), self.code.len());
+            }
+            Instr::Mov { dst, src } => match src {
+                Operand::Reg(r) => self.reg_reg(None, self.wide, &[0x89], num(r), num(dst)),
+                // i386 has no rip relative addressing, the address itself is
+                // the immediate
+                Operand::Label(l) if !self.wide => {
+                    self.code.push(0xB8 + num(dst));
+                    self.relocs.push(Reloc {
+                        offset: self.code.len() as u64,
+                        symbol: l.clone(),
+                        kind: RelocKind::Abs32,
+                        addend: 0,
+                    });
+                    self.bytes(&[0; 4]);
+                }
+                Operand::Imm(i) if !self.wide => {
+                    let imm = u32::try_from(*i)
+                        .or_else(|_| i32::try_from(*i).map(|d| d as u32))
+                        .map_err(|_| self.unencodable(instr))?;
+                    self.code.push(0xB8 + num(dst));
+                    self.bytes(&imm.to_le_bytes());
+                }
+                // mov r32 zero extends, so it covers every unsigned 32-bit value
+                Operand::Imm(i) if (0..=u32::MAX as i64).contains(i) => {
+                    self.rex(false, 0, num(dst), false);
+                    self.code.push(0xB8 + (num(dst) & 7));
+                    self.bytes(&(*i as u32).to_le_bytes());
+                }
+                Operand::Imm(i) if i32::try_from(*i).is_ok() => {
+                    self.reg_reg(None, true, &[0xC7], 0, num(dst));
+                    self.bytes(&(*i as i32).to_le_bytes());
+                }
+                Operand::Imm(i) => {
+                    self.rex(true, 0, num(dst), false);
+                    self.code.push(0xB8 + (num(dst) & 7));
+                    self.bytes(&i.to_le_bytes());
+                }
+                // lea dst, [rip + label]
+                Operand::Label(l) => {
+                    self.rex(true, num(dst), 0, false);
+                    self.bytes(&[0x8D, (num(dst) & 7) << 3 | 0x05]);
+                    self.relocs.push(Reloc {
+                        offset: self.code.len() as u64,
+                        symbol: l.clone(),
+                        kind: RelocKind::Pc32,
+                        addend: -4,
+                    });
+                    self.bytes(&[0; 4]);
+                }
+            },
+            Instr::Load { dst, src, width } if dst.is_float() => {
+                let prefix = if *width == Width::W64 { 0xF2 } else { 0xF3 };
+                self.reg_mem(Some(prefix), false, &[0x0F, 0x10], num(dst), src, false);
+            }
+            Instr::Store { dst, src, width } if src.is_float() => {
+                let prefix = if *width == Width::W64 { 0xF2 } else { 0xF3 };
+                self.reg_mem(Some(prefix), false, &[0x0F, 0x11], num(src), dst, false);
+            }
+            Instr::Load { dst, src, width } => match width {
+                // movzx r32, byte; movsxd; mov
+                Width::W8 => self.reg_mem(None, false, &[0x0F, 0xB6], num(dst), src, false),
+                Width::W32 if !self.wide => {
+                    self.reg_mem(None, false, &[0x8B], num(dst), src, false)
+                }
+                Width::W32 => self.reg_mem(None, true, &[0x63], num(dst), src, false),
+                Width::W64 if !self.wide => return Err(self.unencodable(instr)),
+                Width::W64 => self.reg_mem(None, true, &[0x8B], num(dst), src, false),
+            },
+            Instr::Store { dst, src, width } => match width {
+                // without rex only al, cl, dl and bl have byte forms
+                Width::W8 if !self.wide && num(src) >= 4 => return Err(self.unencodable(instr)),
+                Width::W8 => self.reg_mem(None, false, &[0x88], num(src), dst, true),
+                Width::W64 if !self.wide => return Err(self.unencodable(instr)),
+                Width::W32 => self.reg_mem(None, false, &[0x89], num(src), dst, false),
+                Width::W64 => self.reg_mem(None, true, &[0x89], num(src), dst, false),
+            },
+            Instr::Add { dst, src } => self.alu(instr, 0, 0x01, dst, src)?,
+            Instr::Sub { dst, src } => self.alu(instr, 5, 0x29, dst, src)?,
+            Instr::Cmp { lhs, rhs } => self.alu(instr, 7, 0x39, lhs, rhs)?,
+            Instr::Neg(r) => self.reg_reg(None, self.wide, &[0xF7], 3, num(r)),
+            Instr::FAdd { dst, src } => {
+                self.reg_reg(Some(0xF2), false, &[0x0F, 0x58], num(dst), num(src))
+            }
+            Instr::FSub { dst, src } => {
+                self.reg_reg(Some(0xF2), false, &[0x0F, 0x5C], num(dst), num(src))
+            }
+            Instr::FMul { dst, src } => {
+                self.reg_reg(Some(0xF2), false, &[0x0F, 0x59], num(dst), num(src))
+            }
+            Instr::FDiv { dst, src } => {
+                self.reg_reg(Some(0xF2), false, &[0x0F, 0x5E], num(dst), num(src))
+            }
+            // movapd
+            Instr::FMov { dst, src } => {
+                self.reg_reg(Some(0x66), false, &[0x0F, 0x28], num(dst), num(src))
+            }
+            // ucomisd
+            Instr::FCmp { lhs, rhs } => {
+                self.reg_reg(Some(0x66), false, &[0x0F, 0x2E], num(lhs), num(rhs))
+            }
+            // cvttsd2si, cvtsi2sd, cvtss2sd
+            Instr::FToInt { dst, src } => {
+                self.reg_reg(Some(0xF2), self.wide, &[0x0F, 0x2C], num(dst), num(src))
+            }
+            Instr::IntToF { dst, src } => {
+                self.reg_reg(Some(0xF2), self.wide, &[0x0F, 0x2A], num(dst), num(src))
+            }
+            Instr::FWiden(r) => self.reg_reg(Some(0xF3), false, &[0x0F, 0x5A], num(r), num(r)),
+            Instr::Jump(l) => {
+                self.code.push(0xE9);
+                self.rel32(l);
+            }
+            Instr::JumpIf { cond, target } => {
+                self.bytes(&[0x0F, 0x80 | cc(*cond)]);
+                self.rel32(target);
+            }
+            Instr::Call(l) => {
+                self.code.push(0xE8);
+                self.rel32(l);
+            }
+            Instr::Ret => self.code.push(0xC3),
+            Instr::Push(r) => {
+                self.rex(false, 0, num(r), false);
+                self.code.push(0x50 + (num(r) & 7));
+            }
+            Instr::Pop(r) => {
+                self.rex(false, 0, num(r), false);
+                self.code.push(0x58 + (num(r) & 7));
+            }
+            Instr::Syscall(Trap::Syscall) => self.bytes(&[0x0F, 0x05]),
+            Instr::Syscall(Trap::Int80) => self.bytes(&[0xCD, 0x80]),
+            Instr::Syscall(Trap::Svc) => return Err(self.unencodable(instr)),
+        }
+        Ok(())
+    }
+}
+
+// x86-64 or i386 machine code for the program's text. jumps and calls to
+// text labels are resolved here; label addresses become relocations
+pub fn encode(
+    program: &Program,
+    target: &Target,
+    symbols: &mut Vec<Symbol>,
+) -> Result<(Vec<u8>, Vec<Reloc>), MakeError> {
+    let mut enc = Encoder {
+        target,
+        wide: target.bit == Bit::X64,
+        code: Vec::new(),
+        labels: HashMap::new(),
+        fixups: Vec::new(),
+        relocs: Vec::new(),
+    };
+    enc.labels.insert(program.entry.clone(), 0);
+    symbols.push(Symbol {
+        name: program.entry.clone(),
+        section: Section::Text,
+        offset: 0,
+    });
+    for instr in &program.text {
+        if let Instr::Label(l) = instr {
+            symbols.push(Symbol {
+                name: l.clone(),
+                section: Section::Text,
+                offset: enc.code.len() as u64,
+            });
+        }
+        enc.instr(instr)?;
+    }
+    for (at, label) in std::mem::take(&mut enc.fixups) {
+        match enc.labels.get(&label) {
+            Some(to) => {
+                let rel = *to as i64 - (at as i64 + 4);
+                enc.code[at..at + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+            }
+            None => enc.relocs.push(Reloc {
+                offset: at as u64,
+                symbol: label,
+                kind: RelocKind::Pc32,
+                addend: -4,
+            }),
+        }
+    }
+    Ok((enc.code, enc.relocs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{Arch, Bit, X86Reg, OS};
+
+    fn target() -> Target {
+        Target::new(Bit::X64, Arch::X86, OS::Linux)
+    }
+
+    fn reg(r: X86Reg) -> Register {
+        Register::X86(r)
+    }
+
+    fn assemble(text: Vec<Instr>) -> (Vec<u8>, Vec<Reloc>) {
+        let mut program = Program::new("_start");
+        for instr in text {
+            program.push(instr);
+        }
+        encode(&program, &target(), &mut Vec::new()).unwrap()
+    }
+
+    // expected bytes are what gnu as produces for the same instruction
+    #[test]
+    fn matches_gnu_as() {
+        let mem = |base, offset| Mem {
+            base: reg(base),
+            offset,
+        };
+        let cases: Vec<(Instr, &[u8])> = vec![
+            (
+                Instr::Mov {
+                    dst: reg(X86Reg::Rax),
+                    src: Operand::Imm(60),
+                },
+                &[0xB8, 0x3C, 0x00, 0x00, 0x00],
+            ),
+            (
+                Instr::Mov {
+                    dst: reg(X86Reg::R9),
+                    src: Operand::Reg(reg(X86Reg::Rsp)),
+                },
+                &[0x49, 0x89, 0xE1],
+            ),
+            (
+                Instr::Mov {
+                    dst: reg(X86Reg::Rax),
+                    src: Operand::Imm(-1),
+                },
+                &[0x48, 0xC7, 0xC0, 0xFF, 0xFF, 0xFF, 0xFF],
+            ),
+            (
+                Instr::Load {
+                    dst: reg(X86Reg::Rax),
+                    src: mem(X86Reg::R12, 1),
+                    width: Width::W8,
+                },
+                &[0x41, 0x0F, 0xB6, 0x44, 0x24, 0x01],
+            ),
+            (
+                Instr::Store {
+                    dst: mem(X86Reg::Rdx, 0),
+                    src: reg(X86Reg::Rsi),
+                    width: Width::W8,
+                },
+                &[0x40, 0x88, 0x32],
+            ),
+            (
+                Instr::Load {
+                    dst: reg(X86Reg::Rdi),
+                    src: mem(X86Reg::Rbp, 0),
+                    width: Width::W32,
+                },
+                &[0x48, 0x63, 0x7D, 0x00],
+            ),
+            (
+                Instr::Add {
+                    dst: reg(X86Reg::Rsp),
+                    src: Operand::Imm(352),
+                },
+                &[0x48, 0x81, 0xC4, 0x60, 0x01, 0x00, 0x00],
+            ),
+            (
+                Instr::Cmp {
+                    lhs: reg(X86Reg::Rdx),
+                    rhs: Operand::Imm(-1),
+                },
+                &[0x48, 0x83, 0xFA, 0xFF],
+            ),
+            (
+                Instr::Load {
+                    dst: reg(X86Reg::Xmm1),
+                    src: mem(X86Reg::Rsp, 8),
+                    width: Width::W64,
+                },
+                &[0xF2, 0x0F, 0x10, 0x4C, 0x24, 0x08],
+            ),
+            (
+                Instr::IntToF {
+                    dst: reg(X86Reg::Xmm2),
+                    src: reg(X86Reg::R10),
+                },
+                &[0xF2, 0x49, 0x0F, 0x2A, 0xD2],
+            ),
+            (
+                Instr::FCmp {
+                    lhs: reg(X86Reg::Xmm0),
+                    rhs: reg(X86Reg::Xmm1),
+                },
+                &[0x66, 0x0F, 0x2E, 0xC1],
+            ),
+            (Instr::Push(reg(X86Reg::R12)), &[0x41, 0x54]),
+        ];
+        for (instr, expected) in cases {
+            assert_eq!(assemble(vec![instr.clone()]).0, expected, "{:?}", instr);
+        }
+    }
+
+    #[test]
+    fn jumps_resolve_and_addresses_relocate() {
+        let (code, relocs) = assemble(vec![
+            Instr::Label("top".to_string()),
+            Instr::Mov {
+                dst: reg(X86Reg::Rsi),
+                src: Operand::Label("msg".to_string()),
+            },
+            Instr::Jump("top".to_string()),
+        ]);
+        // lea rsi, [rip + msg]; jmp top
+        assert_eq!(
+            code,
+            [0x48, 0x8D, 0x35, 0, 0, 0, 0, 0xE9, 0xF4, 0xFF, 0xFF, 0xFF]
+        );
+        assert_eq!(
+            relocs,
+            [Reloc {
+                offset: 3,
+                symbol: "msg".to_string(),
+                kind: RelocKind::Pc32,
+                addend: -4,
+            }]
+        );
+    }
+
+    #[test]
+    fn i386_has_no_rex_and_absolute_addresses() {
+        let mut program = Program::new("_start");
+        program.push(Instr::Mov {
+            dst: reg(X86Reg::Rax),
+            src: Operand::Label("msg".to_string()),
+        });
+        program.push(Instr::Load {
+            dst: reg(X86Reg::Rcx),
+            src: Mem {
+                base: reg(X86Reg::Rsp),
+                offset: 4,
+            },
+            width: Width::W32,
+        });
+        program.push(Instr::Sub {
+            dst: reg(X86Reg::Rsp),
+            src: Operand::Imm(32),
+        });
+        program.push(Instr::FToInt {
+            dst: reg(X86Reg::Rdx),
+            src: reg(X86Reg::Xmm1),
+        });
+        let target = Target::new(Bit::X32, Arch::X86, OS::Linux);
+        let (code, relocs) = encode(&program, &target, &mut Vec::new()).unwrap();
+        // mov eax, msg; mov ecx, [esp + 4]; sub esp, 32; cvttsd2si edx, xmm1
+        assert_eq!(
+            code,
+            [0xB8, 0, 0, 0, 0, 0x8B, 0x4C, 0x24, 0x04, 0x83, 0xEC, 0x20, 0xF2, 0x0F, 0x2C, 0xD1]
+        );
+        assert_eq!(
+            relocs,
+            [Reloc {
+                offset: 1,
+                symbol: "msg".to_string(),
+                kind: RelocKind::Abs32,
+                addend: 0,
+            }]
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use crate::{coreasm::Types, emit::Syntax};
+use std::{fmt, io, path::PathBuf};
+
+#[derive(Debug)]
+pub enum MakeError {
+    // no register map exists for this os/arch/bit combination
+    UnsupportedTarget(String),
+    // a target triple that names no Target
+    UnknownTarget(String),
+    // the target's register map has no entry for a role maker needs
+    MissingRegister {
+        target: String,
+        role: String,
+    },
+    // a PrintToken::Variable names something that was never mkvar'd
+    UndefinedVariable(String),
+    // a variable name that can't be an assembly label
+    BadName(String),
+    // two variables with one name, which mkvar never makes but a loaded
+    // program can have
+    DuplicateVariable(String),
+    // a variable's declared type doesn't fit its value or how it is used
+    TypeMismatch {
+        var: String,
+        expected: Types,
+        found: Types,
+    },
+    // the target can't be written in the requested assembler syntax
+    UnsupportedSyntax {
+        target: String,
+        syntax: Syntax,
+    },
+    // the in-process encoder has no encoding for this instruction
+    Unencodable {
+        target: String,
+        instr: String,
+    },
+    // a relocation or the entry point names a label nothing defines
+    UndefinedSymbol(String),
+    // a float asked for more digits than the runtime printer supports
+    BadPrecision {
+        var: String,
+        precision: u32,
+    },
+    // a `break` or `continue` with no loop around it
+    OutsideLoop(String),
+    // a for loop stepping by 0, or by more than its variable's type holds
+    BadStep {
+        var: String,
+        step: i64,
+    },
+    // a program with both `prints` and `statements`, whose order relative to
+    // each other nothing records
+    MixedPrints,
+}
+
+impl fmt::Display for MakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MakeError::UnsupportedTarget(target) => write!(f, "unsupported target: {}", target),
+            MakeError::UnknownTarget(triple) => write!(f, "unknown target triple: {}", triple),
+            MakeError::MissingRegister { target, role } => {
+                write!(f, "target {} has no register for \"{}\"", target, role)
+            }
+            MakeError::UndefinedVariable(name) => write!(f, "undefined variable: {}", name),
+            MakeError::BadName(name) => write!(f, "not a valid variable name: {:?}", name),
+            MakeError::DuplicateVariable(name) => write!(f, "variable declared twice: {}", name),
+            MakeError::TypeMismatch {
+                var,
+                expected,
+                found,
+            } => write!(
+                f,
+                "type mismatch for variable {}: expected {:?}, found {:?}",
+                var, expected, found
+            ),
+            MakeError::UnsupportedSyntax { target, syntax } => {
+                write!(f, "target {} has no {:?} syntax", target, syntax)
+            }
+            MakeError::Unencodable { target, instr } => {
+                write!(f, "cannot encode {} for target {}", instr, target)
+            }
+            MakeError::UndefinedSymbol(name) => write!(f, "undefined symbol: {}", name),
+            MakeError::BadPrecision { var, precision } => write!(
+                f,
+                "precision {} for variable {} is out of range (0..={})",
+                precision,
+                var,
+                crate::runtime::MAX_PRECISION
+            ),
+            MakeError::OutsideLoop(statement) => write!(f, "{} outside a loop", statement),
+            MakeError::BadStep { var, step } => {
+                write!(f, "step {} for loop variable {} is out of range", step, var)
+            }
+            MakeError::MixedPrints => {
+                write!(f, "a program uses either prints or statements, not both")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MakeError {}
+
+// why `build::build` didn't produce an executable
+#[derive(Debug)]
+pub enum BuildError {
+    Make(MakeError),
+    // writing the source or reading the result
+    Io(io::Error),
+    // the assembler or linker couldn't be started, usually not installed
+    Spawn {
+        tool: String,
+        error: io::Error,
+    },
+    // the tool ran and failed; stderr is what it printed
+    Tool {
+        tool: String,
+        status: Option<i32>,
+        stderr: String,
+    },
+    // an intermediate file would have the executable's path
+    Overwrite(PathBuf),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Make(e) => write!(f, "{}", e),
+            BuildError::Io(e) => write!(f, "{}", e),
+            BuildError::Spawn { tool, error } => write!(f, "could not run {}: {}", tool, error),
+            BuildError::Overwrite(path) => write!(
+                f,
+                "{}: is both the executable and an intermediate file",
+                path.display()
+            ),
+            BuildError::Tool {
+                tool,
+                status,
+                stderr,
+            } => {
+                match status {
+                    Some(code) => write!(f, "{} failed with exit status {}", tool, code)?,
+                    None => write!(f, "{} was killed by a signal", tool)?,
+                }
+                if !stderr.is_empty() {
+                    write!(f, ":\n{}", stderr.trim_end())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<MakeError> for BuildError {
+    fn from(e: MakeError) -> Self {
+        BuildError::Make(e)
+    }
+}
+
+impl From<io::Error> for BuildError {
+    fn from(e: io::Error) -> Self {
+        BuildError::Io(e)
+    }
+}
+
+// why `load` couldn't turn a JSON or TOML document into a CoreAsm
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    // a file that's neither .json nor .toml
+    UnknownFormat(String),
+    // it parsed, but isn't a program maker can take
+    Make(MakeError),
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Json(e) => write!(f, "bad json: {}", e),
+            LoadError::Toml(e) => write!(f, "bad toml: {}", e.to_string().trim_end()),
+            LoadError::UnknownFormat(path) => {
+                write!(f, "{}: expected a .json or .toml file", path)
+            }
+            LoadError::Make(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for LoadError {}
+
+#[cfg(feature = "serde")]
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Error> for LoadError {
+    fn from(e: serde_json::Error) -> Self {
+        LoadError::Json(e)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<toml::de::Error> for LoadError {
+    fn from(e: toml::de::Error) -> Self {
+        LoadError::Toml(e)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<MakeError> for LoadError {
+    fn from(e: MakeError) -> Self {
+        LoadError::Make(e)
+    }
+}
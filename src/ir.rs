@@ -0,0 +1,188 @@
+use crate::arch::{Register, Trap, Width};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Reg(Register),
+    Imm(i64),
+    // the address of a label
+    Label(String),
+}
+
+// [base + offset]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mem {
+    pub base: Register,
+    pub offset: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    Eq,
+    Ne,
+    // signed
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    // unsigned
+    Below,
+    BelowEq,
+    Above,
+    AboveEq,
+    // after FCmp: either side was NaN. FCmp otherwise uses the unsigned
+    // conditions, which only mean ordered results once NaN is ruled out
+    Unordered,
+}
+
+// one machine-level instruction, registers already chosen for the target.
+// register operands are word sized unless a Width says otherwise; loads
+// narrower than a word sign extend W32 and zero extend W8. Load and Store
+// also take float registers, where the width picks single or double
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    Label(String),
+    Mov {
+        dst: Register,
+        src: Operand,
+    },
+    Load {
+        dst: Register,
+        src: Mem,
+        width: Width,
+    },
+    Store {
+        dst: Mem,
+        src: Register,
+        width: Width,
+    },
+    Add {
+        dst: Register,
+        src: Operand,
+    },
+    Sub {
+        dst: Register,
+        src: Operand,
+    },
+    Neg(Register),
+    // scalar double arithmetic, dst = dst op src
+    FAdd {
+        dst: Register,
+        src: Register,
+    },
+    FSub {
+        dst: Register,
+        src: Register,
+    },
+    FMul {
+        dst: Register,
+        src: Register,
+    },
+    FDiv {
+        dst: Register,
+        src: Register,
+    },
+    FMov {
+        dst: Register,
+        src: Register,
+    },
+    FCmp {
+        lhs: Register,
+        rhs: Register,
+    },
+    // double to signed word, rounding toward zero
+    FToInt {
+        dst: Register,
+        src: Register,
+    },
+    // signed word to double
+    IntToF {
+        dst: Register,
+        src: Register,
+    },
+    // single to double in place
+    FWiden(Register),
+    Cmp {
+        lhs: Register,
+        rhs: Operand,
+    },
+    Jump(String),
+    JumpIf {
+        cond: Cond,
+        target: String,
+    },
+    Call(String),
+    Ret,
+    Push(Register),
+    Pop(Register),
+    Syscall(Trap),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataValue {
+    // raw bytes; strings are stored as their utf-8 encoding
+    Bytes(Vec<u8>),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    // a run of 64-bit words under one label
+    Table(Vec<i64>),
+}
+
+impl DataValue {
+    // natural alignment, so arm loads never straddle words
+    pub fn align(&self) -> usize {
+        match self {
+            DataValue::Bytes(_) => 1,
+            DataValue::I32(_) | DataValue::F32(_) => 4,
+            DataValue::I64(_) | DataValue::F64(_) | DataValue::Table(_) => 8,
+        }
+    }
+
+    // the little endian bytes the item assembles to
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            DataValue::Bytes(b) => b.clone(),
+            DataValue::I32(i) => i.to_le_bytes().to_vec(),
+            DataValue::I64(i) => i.to_le_bytes().to_vec(),
+            DataValue::F32(f) => f.to_le_bytes().to_vec(),
+            DataValue::F64(f) => f.to_le_bytes().to_vec(),
+            DataValue::Table(t) => t.iter().flat_map(|i| i.to_le_bytes()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataItem {
+    pub label: String,
+    pub value: DataValue,
+}
+
+// everything maker knows about a program once CoreAsm has been lowered
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub entry: String,
+    pub data: Vec<DataItem>,
+    pub text: Vec<Instr>,
+}
+
+impl Program {
+    pub fn new(entry: &str) -> Self {
+        Program {
+            entry: entry.to_string(),
+            data: Vec::new(),
+            text: Vec::new(),
+        }
+    }
+
+    pub fn data(&mut self, label: &str, value: DataValue) {
+        self.data.push(DataItem {
+            label: label.to_string(),
+            value,
+        });
+    }
+
+    pub fn push(&mut self, instr: Instr) {
+        self.text.push(instr);
+    }
+}
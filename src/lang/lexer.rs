@@ -0,0 +1,375 @@
+use super::{Diagnostic, Span};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tok {
+    Ident(String),
+    Str(String),
+    // the sign is a separate token, so integers are kept unsigned
+    Int(u64),
+    Float(f64),
+    Colon,
+    Semi,
+    Comma,
+    Eq,
+    Plus,
+    Minus,
+    PlusEq,
+    MinusEq,
+    DotDot,
+    LBrace,
+    RBrace,
+    // comparisons
+    EqEq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eof,
+}
+
+impl Tok {
+    // how the token reads in "expected ..., found ..."
+    pub fn describe(&self) -> String {
+        match self {
+            Tok::Ident(name) => format!("`{}`", name),
+            Tok::Str(_) => "a string".to_string(),
+            Tok::Int(_) | Tok::Float(_) => "a number".to_string(),
+            Tok::Colon => "`:`".to_string(),
+            Tok::Semi => "`;`".to_string(),
+            Tok::Comma => "`,`".to_string(),
+            Tok::Eq => "`=`".to_string(),
+            Tok::Plus => "`+`".to_string(),
+            Tok::Minus => "`-`".to_string(),
+            Tok::PlusEq => "`+=`".to_string(),
+            Tok::MinusEq => "`-=`".to_string(),
+            Tok::DotDot => "`..`".to_string(),
+            Tok::LBrace => "`{`".to_string(),
+            Tok::RBrace => "`}`".to_string(),
+            Tok::EqEq => "`==`".to_string(),
+            Tok::Ne => "`!=`".to_string(),
+            Tok::Lt => "`<`".to_string(),
+            Tok::Le => "`<=`".to_string(),
+            Tok::Gt => "`>`".to_string(),
+            Tok::Ge => "`>=`".to_string(),
+            Tok::Eof => "the end of the file".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub tok: Tok,
+    pub span: Span,
+}
+
+struct Lexer<'a> {
+    source: &'a str,
+    pos: usize,
+    line: usize,
+    col: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Lexer<'_> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn peek2(&self) -> Option<char> {
+        self.source[self.pos..].chars().nth(1)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    fn bump_while(&mut self, keep: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&keep) {
+            self.bump();
+        }
+    }
+
+    // a span from `start` (taken before the token) up to here
+    fn since(&self, start: Span) -> Span {
+        Span {
+            end: self.pos,
+            ..start
+        }
+    }
+
+    fn here(&self) -> Span {
+        Span {
+            start: self.pos,
+            end: self.pos,
+            line: self.line,
+            col: self.col,
+        }
+    }
+
+    fn string(&mut self, start: Span) -> Option<Tok> {
+        let mut text = String::new();
+        loop {
+            let at = self.here();
+            match self.bump() {
+                Some('"') => return Some(Tok::Str(text)),
+                Some('\\') => {
+                    let escaped = match self.bump() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('0') => '\0',
+                        Some(c @ ('"' | '\\')) => c,
+                        Some('\n') | None => break,
+                        Some(c) => {
+                            let span = self.since(at);
+                            self.diagnostics
+                                .push(Diagnostic::new(span, format!("unknown escape `\\{}`", c)));
+                            continue;
+                        }
+                    };
+                    text.push(escaped);
+                }
+                Some('\n') | None => break,
+                Some(c) => text.push(c),
+            }
+        }
+        let span = self.since(start);
+        self.diagnostics
+            .push(Diagnostic::new(span, "unterminated string"));
+        None
+    }
+
+    fn number(&mut self, start: Span) -> Option<Tok> {
+        self.bump_while(|c| c.is_ascii_digit());
+        let mut float = false;
+        if self.peek() == Some('.') && self.peek2().is_some_and(|c| c.is_ascii_digit()) {
+            float = true;
+            self.bump();
+            self.bump_while(|c| c.is_ascii_digit());
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            let digits_at = match self.peek2() {
+                Some('+' | '-') => 2,
+                _ => 1,
+            };
+            if self.source[self.pos..]
+                .chars()
+                .nth(digits_at)
+                .is_some_and(|c| c.is_ascii_digit())
+            {
+                float = true;
+                for _ in 0..digits_at {
+                    self.bump();
+                }
+                self.bump_while(|c| c.is_ascii_digit());
+            }
+        }
+        let span = self.since(start);
+        let text = &self.source[span.start..span.end];
+        if float {
+            return text.parse().ok().map(Tok::Float);
+        }
+        match text.parse() {
+            Ok(n) => Some(Tok::Int(n)),
+            Err(_) => {
+                self.diagnostics
+                    .push(Diagnostic::new(span, "number is too large"));
+                None
+            }
+        }
+    }
+
+    // the next token, or None for one that was reported as a mistake; the
+    // outer None is the end of the source
+    fn next(&mut self) -> Option<(Option<Tok>, Span)> {
+        loop {
+            self.bump_while(char::is_whitespace);
+            if self.peek() == Some('/') && self.peek2() == Some('/') {
+                self.bump_while(|c| c != '\n');
+            } else {
+                break;
+            }
+        }
+        let start = self.here();
+        let c = self.bump()?;
+        let tok = match c {
+            ':' => Some(Tok::Colon),
+            ';' => Some(Tok::Semi),
+            ',' => Some(Tok::Comma),
+            '=' if self.peek() == Some('=') => {
+                self.bump();
+                Some(Tok::EqEq)
+            }
+            '!' if self.peek() == Some('=') => {
+                self.bump();
+                Some(Tok::Ne)
+            }
+            '<' if self.peek() == Some('=') => {
+                self.bump();
+                Some(Tok::Le)
+            }
+            '>' if self.peek() == Some('=') => {
+                self.bump();
+                Some(Tok::Ge)
+            }
+            '+' if self.peek() == Some('=') => {
+                self.bump();
+                Some(Tok::PlusEq)
+            }
+            '-' if self.peek() == Some('=') => {
+                self.bump();
+                Some(Tok::MinusEq)
+            }
+            '=' => Some(Tok::Eq),
+            '<' => Some(Tok::Lt),
+            '>' => Some(Tok::Gt),
+            '+' => Some(Tok::Plus),
+            '-' => Some(Tok::Minus),
+            '.' if self.peek() == Some('.') => {
+                self.bump();
+                Some(Tok::DotDot)
+            }
+            '{' => Some(Tok::LBrace),
+            '}' => Some(Tok::RBrace),
+            '"' => self.string(start),
+            c if c.is_ascii_digit() => self.number(start),
+            c if c.is_alphabetic() || c == '_' => {
+                self.bump_while(|c| c.is_alphanumeric() || c == '_');
+                let span = self.since(start);
+                Some(Tok::Ident(self.source[span.start..span.end].to_string()))
+            }
+            c => {
+                let span = self.since(start);
+                self.diagnostics.push(Diagnostic::new(
+                    span,
+                    format!("unexpected character `{}`", c),
+                ));
+                None
+            }
+        };
+        Some((tok, self.since(start)))
+    }
+}
+
+// the tokens of a source file, always ending in Eof
+pub fn lex(source: &str) -> Result<Vec<Token>, Vec<Diagnostic>> {
+    let mut lexer = Lexer {
+        source,
+        pos: 0,
+        line: 1,
+        col: 1,
+        diagnostics: Vec::new(),
+    };
+    let mut tokens = Vec::new();
+    while let Some((tok, span)) = lexer.next() {
+        if let Some(tok) = tok {
+            tokens.push(Token { tok, span });
+        }
+    }
+    if !lexer.diagnostics.is_empty() {
+        return Err(lexer.diagnostics);
+    }
+    tokens.push(Token {
+        tok: Tok::Eof,
+        span: lexer.here(),
+    });
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toks(source: &str) -> Vec<Tok> {
+        lex(source).unwrap().into_iter().map(|t| t.tok).collect()
+    }
+
+    #[test]
+    fn tokens_and_spans() {
+        assert_eq!(
+            toks("var x: f64 = -2.5e3; // done\nprint \"a\\tb\\n\", x:2;"),
+            [
+                Tok::Ident("var".to_string()),
+                Tok::Ident("x".to_string()),
+                Tok::Colon,
+                Tok::Ident("f64".to_string()),
+                Tok::Eq,
+                Tok::Minus,
+                Tok::Float(2.5e3),
+                Tok::Semi,
+                Tok::Ident("print".to_string()),
+                Tok::Str("a\tb\n".to_string()),
+                Tok::Comma,
+                Tok::Ident("x".to_string()),
+                Tok::Colon,
+                Tok::Int(2),
+                Tok::Semi,
+                Tok::Eof,
+            ]
+        );
+        assert_eq!(
+            toks("if a<=b{}else!=<>= >==0..-2 n+=1-=m+-3"),
+            [
+                Tok::Ident("if".to_string()),
+                Tok::Ident("a".to_string()),
+                Tok::Le,
+                Tok::Ident("b".to_string()),
+                Tok::LBrace,
+                Tok::RBrace,
+                Tok::Ident("else".to_string()),
+                Tok::Ne,
+                Tok::Lt,
+                Tok::Ge,
+                Tok::Ge,
+                Tok::Eq,
+                Tok::Int(0),
+                Tok::DotDot,
+                Tok::Minus,
+                Tok::Int(2),
+                Tok::Ident("n".to_string()),
+                Tok::PlusEq,
+                Tok::Int(1),
+                Tok::MinusEq,
+                Tok::Ident("m".to_string()),
+                Tok::Plus,
+                Tok::Minus,
+                Tok::Int(3),
+                Tok::Eof,
+            ]
+        );
+        let tokens = lex("exit\n  7;").unwrap();
+        assert_eq!(
+            tokens[1].span,
+            Span {
+                start: 7,
+                end: 8,
+                line: 2,
+                col: 3
+            }
+        );
+    }
+
+    #[test]
+    fn every_bad_token_is_reported() {
+        let errors = lex("print \"a\\q\", @;\nprint \"open\n99999999999999999999;").unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "1:9: unknown escape `\\q`",
+                "1:14: unexpected character `@`",
+                "2:7: unterminated string",
+                "3:1: number is too large",
+            ]
+        );
+    }
+}
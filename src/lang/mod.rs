@@ -0,0 +1,126 @@
+// a small source language for CoreAsm programs:
+//
+//   // comments run to the end of the line
+//   var name: string = "joy";
+//   var pi: f64 = 3.14159;
+//   var n: i32 = 7;
+//   print "Hello ", name, "!\n";
+//   if n < 10 {
+//       print "pi is ", pi:2, "\n";
+//   } else if unsigned n > 4000000000 {
+//       print "huge\n";
+//   } else {
+//       print n, "\n";
+//   }
+//   for n in 0..10 step 2 {
+//       if n == 4 { continue; }
+//       print n, "\n";
+//   }
+//   while n > 0 {
+//       break;
+//   }
+//   exit 0;
+//
+// variables are declared before they're used and outside blocks, a float
+// takes its precision after a colon, `if` and `while` compare integer
+// variables and constants (signed, unless it says unsigned), `for` counts
+// an integer variable up to (or with a negative step, down to) the end of
+// its range, and exit (which must come last) takes a status from 0 to 255
+mod lexer;
+mod parser;
+
+use crate::coreasm::CoreAsm;
+use std::fmt;
+
+// where something sits in the source: a byte range, and the line and column
+// (both counted from 1, columns in characters) it starts at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    fn new(span: Span, message: impl Into<String>) -> Self {
+        Diagnostic {
+            message: message.into(),
+            span,
+        }
+    }
+
+    // the message with the offending line underneath and the span underlined:
+    //
+    //   hello.casm:3:7: undefined variable `nmae`
+    //     |
+    //   3 | print nmae;
+    //     |       ^^^^
+    pub fn render(&self, source: &str, path: &str) -> String {
+        let line = source.lines().nth(self.span.line - 1).unwrap_or("");
+        let number = self.span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        let width = source
+            .get(self.span.start..self.span.end)
+            .map_or(1, |s| s.lines().next().unwrap_or("").chars().count())
+            .max(1);
+        // tabs stay tabs so the caret lines up under them
+        let pad: String = line
+            .chars()
+            .take(self.span.col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!(
+            "{}:{}:{}: {}\n{} |\n{} | {}\n{} | {}{}",
+            path,
+            self.span.line,
+            self.span.col,
+            self.message,
+            gutter,
+            number,
+            line,
+            gutter,
+            pad,
+            "^".repeat(width)
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.col, self.message)
+    }
+}
+
+// parses a whole program; on failure every mistake found is returned, in
+// source order
+pub fn parse(source: &str) -> Result<CoreAsm, Vec<Diagnostic>> {
+    let tokens = lexer::lex(source)?;
+    parser::parse(&tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_point_at_the_source() {
+        let source = "var n: i32 = 1;\nprint \"n = \", nmae;\n";
+        let diagnostics = parse(source).err().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "2:15: undefined variable `nmae`"
+        );
+        assert_eq!(
+            diagnostics[0].render(source, "n.casm"),
+            "n.casm:2:15: undefined variable `nmae`\n  |\n2 | print \"n = \", nmae;\n  |               ^^^^"
+        );
+    }
+}
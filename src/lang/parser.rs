@@ -0,0 +1,930 @@
+use super::{
+    lexer::{Tok, Token},
+    Diagnostic, Span,
+};
+use crate::{
+    coreasm::{
+        is_valid_name, Compare, Condition, CoreAsm, Print, PrintToken, Statement, Types, Value,
+        VarValue,
+    },
+    runtime::MAX_PRECISION,
+};
+
+// the type names as they're written in the source
+const TYPES: [(&str, Types); 5] = [
+    ("string", Types::String),
+    ("i32", Types::I32),
+    ("i64", Types::I64),
+    ("f32", Types::F32),
+    ("f64", Types::F64),
+];
+
+fn type_name(ty: Types) -> &'static str {
+    TYPES.iter().find(|(_, t)| *t == ty).unwrap().0
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    asm: CoreAsm,
+    // set by exit, so whatever follows it can be pointed out once
+    exited: bool,
+    // how many loops the statement being parsed is inside
+    loops: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &'a Token {
+        &self.tokens[self.pos]
+    }
+
+    // the last token is always Eof, and it's never stepped past
+    fn bump(&mut self) -> &'a Token {
+        let token = &self.tokens[self.pos];
+        if token.tok != Tok::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn unexpected(&self, token: &Token, what: &str) -> Diagnostic {
+        Diagnostic::new(
+            token.span,
+            format!("expected {}, found {}", what, token.tok.describe()),
+        )
+    }
+
+    fn expect(&mut self, want: Tok, what: &str) -> Result<Span, Diagnostic> {
+        if self.peek().tok != want {
+            return Err(self.unexpected(self.peek(), what));
+        }
+        Ok(self.bump().span)
+    }
+
+    fn ident(&mut self, what: &str) -> Result<(&'a str, Span), Diagnostic> {
+        match self.bump() {
+            Token {
+                tok: Tok::Ident(name),
+                span,
+            } => Ok((name, *span)),
+            token => Err(self.unexpected(token, what)),
+        }
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.peek().tok, Tok::Ident(name) if name == keyword)
+    }
+
+    // after a mistake, skips to the end of the statement it was in: past its
+    // `;` or its block, or up to the `}` of the block around it
+    fn recover(&mut self) {
+        if self.pos > 0 && self.tokens[self.pos - 1].tok == Tok::Semi {
+            return;
+        }
+        let mut depth = 0;
+        loop {
+            match self.peek().tok {
+                Tok::Eof => return,
+                Tok::RBrace if depth == 0 => return,
+                _ => {}
+            }
+            match self.bump().tok {
+                Tok::Semi if depth == 0 => return,
+                Tok::LBrace => depth += 1,
+                Tok::RBrace => {
+                    depth -= 1;
+                    if depth == 0 && !self.at_keyword("else") {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // one statement; declarations and exit change the program as a whole
+    // rather than adding a statement, and only come outside blocks, while
+    // `n = a + b` takes two
+    fn statement(&mut self, top: bool) -> Result<Vec<Statement>, Diagnostic> {
+        let token = self.bump();
+        if std::mem::take(&mut self.exited) {
+            self.diagnostics.push(Diagnostic::new(
+                token.span,
+                "unreachable statement; `exit` has to come last",
+            ));
+        }
+        let keyword = match &token.tok {
+            Tok::Ident(keyword) => keyword.as_str(),
+            _ => "",
+        };
+        match keyword {
+            "var" | "exit" if !top => Err(Diagnostic::new(
+                token.span,
+                format!("`{}` can't be inside a block", keyword),
+            )),
+            "var" => self.var().map(|_| Vec::new()),
+            "exit" => self.exit().map(|_| Vec::new()),
+            "print" => self.print().map(|s| vec![s]),
+            "if" => self.if_else().map(|s| vec![s]),
+            "while" => self.while_loop().map(|s| vec![s]),
+            "for" => self.for_loop().map(|s| vec![s]),
+            "break" | "continue" if self.loops == 0 => Err(Diagnostic::new(
+                token.span,
+                format!("`{}` has to be inside a loop", keyword),
+            )),
+            "break" | "continue" => {
+                self.expect(Tok::Semi, "`;`")?;
+                Ok(vec![match keyword {
+                    "break" => Statement::Break,
+                    _ => Statement::Continue,
+                }])
+            }
+            // anything else named is a variable being assigned
+            name if !name.is_empty()
+                && matches!(self.peek().tok, Tok::Eq | Tok::PlusEq | Tok::MinusEq) =>
+            {
+                self.assignment(name, token.span)
+            }
+            _ => Err(self.unexpected(
+                token,
+                "`var`, `print`, `if`, `while`, `for`, `break`, `continue`, `exit` or an assignment",
+            )),
+        }
+    }
+
+    // { statement... }
+    fn block(&mut self) -> Result<Vec<Statement>, Diagnostic> {
+        self.expect(Tok::LBrace, "`{`")?;
+        let mut statements = Vec::new();
+        while !matches!(self.peek().tok, Tok::RBrace | Tok::Eof) {
+            match self.statement(false) {
+                Ok(statement) => statements.extend(statement),
+                Err(diagnostic) => {
+                    self.diagnostics.push(diagnostic);
+                    self.recover();
+                }
+            }
+        }
+        self.expect(Tok::RBrace, "`}`")?;
+        Ok(statements)
+    }
+
+    // a loop's block, inside which break and continue are allowed
+    fn loop_body(&mut self) -> Result<Vec<Statement>, Diagnostic> {
+        self.loops += 1;
+        let body = self.block();
+        self.loops -= 1;
+        body
+    }
+
+    // [unsigned] a < b
+    fn condition(&mut self) -> Result<Condition, Diagnostic> {
+        let unsigned = self.at_keyword("unsigned");
+        if unsigned {
+            self.bump();
+        }
+        let left = self.operand(unsigned, "compared")?;
+        let token = self.bump();
+        let compare = match token.tok {
+            Tok::EqEq => Compare::Eq,
+            Tok::Ne => Compare::Ne,
+            Tok::Lt => Compare::Lt,
+            Tok::Le => Compare::Le,
+            Tok::Gt => Compare::Gt,
+            Tok::Ge => Compare::Ge,
+            _ => return Err(self.unexpected(token, "`==`, `!=`, `<`, `<=`, `>` or `>=`")),
+        };
+        let right = self.operand(unsigned, "compared")?;
+        Ok(Condition {
+            left,
+            compare,
+            right,
+            unsigned,
+        })
+    }
+
+    // if condition { ... } else if ... { ... } else { ... }
+    fn if_else(&mut self) -> Result<Statement, Diagnostic> {
+        let condition = self.condition()?;
+        let then = self.block()?;
+        let mut otherwise = Vec::new();
+        if self.at_keyword("else") {
+            self.bump();
+            if self.at_keyword("if") {
+                self.bump();
+                otherwise.push(self.if_else()?);
+            } else {
+                otherwise = self.block()?;
+            }
+        }
+        Ok(Statement::If {
+            condition,
+            then,
+            otherwise,
+        })
+    }
+
+    // while condition { ... }
+    fn while_loop(&mut self) -> Result<Statement, Diagnostic> {
+        let condition = self.condition()?;
+        let body = self.loop_body()?;
+        Ok(Statement::While { condition, body })
+    }
+
+    // for i in from..to { ... } or for i in from..to step n { ... }
+    fn for_loop(&mut self) -> Result<Statement, Diagnostic> {
+        let (var, var_span) = self.ident("a loop variable")?;
+        let narrow = match self.asm.data.get(var).map(|v| v.var_type) {
+            Some(Types::I32) => true,
+            Some(Types::I64) => false,
+            Some(ty) => {
+                return Err(Diagnostic::new(
+                    var_span,
+                    format!(
+                        "`{}` is {}; loop variables have to be i32 or i64",
+                        var,
+                        type_name(ty)
+                    ),
+                ))
+            }
+            None => {
+                return Err(Diagnostic::new(
+                    var_span,
+                    format!("undefined variable `{}`", var),
+                ))
+            }
+        };
+        if !self.at_keyword("in") {
+            return Err(self.unexpected(self.peek(), "`in`"));
+        }
+        self.bump();
+        let from_span = self.peek().span;
+        let from = self.operand(false, "compared")?;
+        // the start is stored in the variable, so it has to fit
+        if narrow && !self.fits_i32(&from) {
+            return Err(Diagnostic::new(
+                from_span,
+                format!("start is out of range for `{}`, an i32", var),
+            ));
+        }
+        self.expect(Tok::DotDot, "`..`")?;
+        let to_span = self.peek().span;
+        let to = self.operand(false, "compared")?;
+        // nor can it reach an end it can't hold
+        if narrow && !self.fits_i32(&to) {
+            return Err(Diagnostic::new(
+                to_span,
+                format!("end is out of range for `{}`, an i32", var),
+            ));
+        }
+        let mut step = 1;
+        if self.at_keyword("step") {
+            self.bump();
+            let span = self.peek().span;
+            let Value::Const(n) = self.operand(false, "compared")? else {
+                return Err(Diagnostic::new(span, "step has to be a constant"));
+            };
+            if n == 0 {
+                return Err(Diagnostic::new(span, "step can't be 0"));
+            }
+            if narrow && i32::try_from(n).is_err() {
+                return Err(Diagnostic::new(
+                    span,
+                    format!("step is out of range for `{}`, an i32", var),
+                ));
+            }
+            step = n;
+        }
+        let body = self.loop_body()?;
+        Ok(Statement::For {
+            var: var.to_string(),
+            from,
+            to,
+            step,
+            body,
+        })
+    }
+
+    // whether an i32 variable can hold a value: another i32 variable or a
+    // constant in range
+    fn fits_i32(&self, value: &Value) -> bool {
+        match value {
+            Value::Var(name) => self.asm.data.get(name).map(|v| v.var_type) == Some(Types::I32),
+            Value::Const(n) => i32::try_from(*n).is_ok(),
+        }
+    }
+
+    // n = a; n = a + b; n = a - b; n += a; n -= a;
+    fn assignment(&mut self, var: &str, span: Span) -> Result<Vec<Statement>, Diagnostic> {
+        let narrow = match self.asm.data.get(var).map(|v| v.var_type) {
+            Some(Types::I32) => true,
+            Some(Types::I64) => false,
+            Some(ty) => {
+                return Err(Diagnostic::new(
+                    span,
+                    format!(
+                        "`{}` is {}; only i32 and i64 variables can be assigned",
+                        var,
+                        type_name(ty)
+                    ),
+                ))
+            }
+            None => {
+                return Err(Diagnostic::new(
+                    span,
+                    format!("undefined variable `{}`", var),
+                ))
+            }
+        };
+        // each value is stored in or added to the variable, so it has to fit
+        let value = |parser: &mut Self, verb| {
+            let span = parser.peek().span;
+            let value = parser.operand(false, verb)?;
+            if narrow && !parser.fits_i32(&value) {
+                return Err(Diagnostic::new(
+                    span,
+                    format!("value is out of range for `{}`, an i32", var),
+                ));
+            }
+            Ok((value, span))
+        };
+        let this = Value::Var(var.to_string());
+        let set = |value| Statement::Set {
+            var: var.to_string(),
+            value,
+        };
+        let add = |value| Statement::Add {
+            var: var.to_string(),
+            value,
+        };
+        let sub = |value| Statement::Sub {
+            var: var.to_string(),
+            value,
+        };
+        let statements = match self.bump().tok {
+            Tok::PlusEq => vec![add(value(self, "added")?.0)],
+            Tok::MinusEq => vec![sub(value(self, "subtracted")?.0)],
+            _ => {
+                let (first, _) = value(self, "assigned")?;
+                match self.peek().tok {
+                    Tok::Plus | Tok::Minus => {
+                        let plus = self.bump().tok == Tok::Plus;
+                        let verb = if plus { "added" } else { "subtracted" };
+                        let (second, second_span) = value(self, verb)?;
+                        // the variable is set to the first value before the
+                        // second is added, so it can't be the second itself
+                        match (first == this, second == this, plus) {
+                            (true, _, true) => vec![add(second)],
+                            (true, _, false) => vec![sub(second)],
+                            (false, true, true) => vec![add(first)],
+                            (false, true, false) => {
+                                return Err(Diagnostic::new(
+                                    second_span,
+                                    format!(
+                                        "`{}` can't be subtracted from another value while it's being set",
+                                        var
+                                    ),
+                                ))
+                            }
+                            (false, false, true) => vec![set(first), add(second)],
+                            (false, false, false) => vec![set(first), sub(second)],
+                        }
+                    }
+                    _ => vec![set(first)],
+                }
+            }
+        };
+        self.expect(Tok::Semi, "`;`")?;
+        Ok(statements)
+    }
+
+    // an integer variable or constant; unsigned comparisons take constants
+    // up to u64::MAX, kept as their bits
+    fn operand(&mut self, unsigned: bool, verb: &str) -> Result<Value, Diagnostic> {
+        let start = self.peek().span;
+        let negative = self.peek().tok == Tok::Minus;
+        if negative {
+            self.bump();
+        }
+        let token = self.bump();
+        let span = Span {
+            end: token.span.end,
+            ..start
+        };
+        match &token.tok {
+            Tok::Int(n) => {
+                let n = if negative {
+                    -i128::from(*n)
+                } else {
+                    i128::from(*n)
+                };
+                i64::try_from(n)
+                    .ok()
+                    .or_else(|| u64::try_from(n).ok().filter(|_| unsigned).map(|n| n as i64))
+                    .map(Value::Const)
+                    .ok_or_else(|| Diagnostic::new(span, "constant is out of range"))
+            }
+            Tok::Ident(name) if !negative => {
+                match self.asm.data.get(name).map(|var| var.var_type) {
+                    Some(Types::I32 | Types::I64) => Ok(Value::Var(name.clone())),
+                    Some(ty) => Err(Diagnostic::new(
+                        span,
+                        format!(
+                            "`{}` is {}; only i32 and i64 variables can be {}",
+                            name,
+                            type_name(ty),
+                            verb
+                        ),
+                    )),
+                    None => Err(Diagnostic::new(
+                        span,
+                        format!("undefined variable `{}`", name),
+                    )),
+                }
+            }
+            _ => Err(self.unexpected(token, "an integer variable or constant")),
+        }
+    }
+
+    // var name: type = value;
+    fn var(&mut self) -> Result<(), Diagnostic> {
+        let (name, name_span) = self.ident("a variable name")?;
+        if !is_valid_name(name) {
+            return Err(Diagnostic::new(
+                name_span,
+                format!("`{}` can't be used as a variable name", name),
+            ));
+        }
+        self.expect(Tok::Colon, "`:` and a type")?;
+        let (ty, ty_span) = self.ident("a type")?;
+        let Some(&(_, ty)) = TYPES.iter().find(|(n, _)| *n == ty) else {
+            return Err(Diagnostic::new(
+                ty_span,
+                format!(
+                    "unknown type `{}`; expected string, i32, i64, f32 or f64",
+                    ty
+                ),
+            ));
+        };
+        self.expect(Tok::Eq, "`=`")?;
+        let value = self.value(ty)?;
+        self.expect(Tok::Semi, "`;`")?;
+        if self.asm.data.get(name).is_some() {
+            return Err(Diagnostic::new(
+                name_span,
+                format!("`{}` is already declared", name),
+            ));
+        }
+        self.asm.data.mkvar(name.to_string(), ty, value);
+        Ok(())
+    }
+
+    // a literal of the declared type; integers fit it and floats may be
+    // written without a point
+    fn value(&mut self, ty: Types) -> Result<VarValue, Diagnostic> {
+        let expected = format!("{} value", type_name(ty));
+        if ty == Types::String {
+            return match self.bump() {
+                Token {
+                    tok: Tok::Str(s), ..
+                } => Ok(VarValue::String(s.clone())),
+                token => Err(self.unexpected(token, &format!("a {}", expected))),
+            };
+        }
+        let start = self.peek().span;
+        let negative = self.peek().tok == Tok::Minus;
+        if negative {
+            self.bump();
+        }
+        let token = self.bump();
+        let span = Span {
+            end: token.span.end,
+            ..start
+        };
+        let sign = if negative { -1.0 } else { 1.0 };
+        let out_of_range =
+            || Diagnostic::new(span, format!("value is out of range for {}", type_name(ty)));
+        match (ty, &token.tok) {
+            (Types::I32 | Types::I64, Tok::Int(n)) => {
+                let n = if negative {
+                    -i128::from(*n)
+                } else {
+                    i128::from(*n)
+                };
+                match ty {
+                    Types::I32 => i32::try_from(n).map(VarValue::I32),
+                    _ => i64::try_from(n).map(VarValue::I64),
+                }
+                .map_err(|_| out_of_range())
+            }
+            (Types::F32, Tok::Int(n)) => Ok(VarValue::F32(sign as f32 * *n as f32)),
+            (Types::F32, Tok::Float(x)) => Ok(VarValue::F32((sign * x) as f32)),
+            (Types::F64, Tok::Int(n)) => Ok(VarValue::F64(sign * *n as f64)),
+            (Types::F64, Tok::Float(x)) => Ok(VarValue::F64(sign * x)),
+            _ => Err(self.unexpected(token, &format!("an {}", expected))),
+        }
+    }
+
+    // print item, item, ...;
+    fn print(&mut self) -> Result<Statement, Diagnostic> {
+        let mut print = Print::new();
+        if self.peek().tok != Tok::Semi {
+            loop {
+                print.add_token(self.print_item()?);
+                if self.peek().tok != Tok::Comma {
+                    break;
+                }
+                self.bump();
+            }
+        }
+        self.expect(Tok::Semi, "`,` or `;`")?;
+        Ok(Statement::Print(print))
+    }
+
+    // "text", a variable, or a float variable with its precision: x:2
+    fn print_item(&mut self) -> Result<PrintToken, Diagnostic> {
+        let token = self.bump();
+        let name = match &token.tok {
+            Tok::Str(s) => return Ok(PrintToken::Text(s.clone())),
+            Tok::Ident(name) => name,
+            _ => return Err(self.unexpected(token, "a string or a variable")),
+        };
+        let Some(ty) = self.asm.data.get(name).map(|var| var.var_type) else {
+            return Err(Diagnostic::new(
+                token.span,
+                format!("undefined variable `{}`", name),
+            ));
+        };
+        if self.peek().tok != Tok::Colon {
+            return Ok(PrintToken::Variable(name.clone()));
+        }
+        self.bump();
+        if !matches!(ty, Types::F32 | Types::F64) {
+            return Err(Diagnostic::new(
+                token.span,
+                format!(
+                    "`{}` is {}; only f32 and f64 variables take a precision",
+                    name,
+                    type_name(ty)
+                ),
+            ));
+        }
+        let precision = self.bump();
+        match precision.tok {
+            Tok::Int(n) if n <= u64::from(MAX_PRECISION) => Ok(PrintToken::Float {
+                var: name.clone(),
+                precision: n as u32,
+            }),
+            Tok::Int(_) => Err(Diagnostic::new(
+                precision.span,
+                format!("precision is more than {} digits", MAX_PRECISION),
+            )),
+            _ => Err(self.unexpected(precision, "a precision")),
+        }
+    }
+
+    // exit; or exit status;
+    fn exit(&mut self) -> Result<(), Diagnostic> {
+        let code = match self.peek() {
+            Token {
+                tok: Tok::Int(n),
+                span,
+            } => {
+                let code = u8::try_from(*n)
+                    .map_err(|_| Diagnostic::new(*span, "exit status has to be from 0 to 255"))?;
+                self.bump();
+                code
+            }
+            _ => 0,
+        };
+        self.expect(Tok::Semi, "`;`")?;
+        self.asm.exit_with(code);
+        self.exited = true;
+        Ok(())
+    }
+}
+
+pub fn parse(tokens: &[Token]) -> Result<CoreAsm, Vec<Diagnostic>> {
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        asm: CoreAsm::new(),
+        exited: false,
+        loops: 0,
+        diagnostics: Vec::new(),
+    };
+    while parser.peek().tok != Tok::Eof {
+        match parser.statement(true) {
+            Ok(statement) => parser.asm.statements.extend(statement),
+            Err(diagnostic) => {
+                parser.diagnostics.push(diagnostic);
+                parser.recover();
+            }
+        }
+    }
+    if parser.diagnostics.is_empty() {
+        Ok(parser.asm)
+    } else {
+        Err(parser.diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::parse;
+
+    #[test]
+    fn programs_become_coreasm() {
+        let asm = parse(
+            "var name: string = \"joy\";
+             var n: i32 = -2147483648;
+             var big: i64 = 9000000000;
+             var pi: f64 = 3.14159;
+             var half: f32 = -1;
+             print \"Hello \", name, \"!\\n\";
+             print n, big, pi:3, half;
+             print;
+             exit 3;",
+        )
+        .unwrap();
+        let value = |name| &asm.data.get(name).unwrap().value;
+        assert!(matches!(value("name"), VarValue::String(s) if s == "joy"));
+        assert!(matches!(value("n"), VarValue::I32(i32::MIN)));
+        assert!(matches!(value("big"), VarValue::I64(9_000_000_000)));
+        assert!(matches!(value("half"), VarValue::F32(x) if *x == -1.0));
+        let prints: Vec<&Print> = asm
+            .statements
+            .iter()
+            .map(|statement| match statement {
+                Statement::Print(print) => print,
+                _ => panic!("expected only prints"),
+            })
+            .collect();
+        assert_eq!(prints.len(), 3);
+        let tokens = &prints[0].tokens;
+        assert!(matches!(&tokens[2], PrintToken::Text(t) if t == "!\n"));
+        let tokens = &prints[1].tokens;
+        assert!(matches!(&tokens[2], PrintToken::Float { var, precision: 3 } if var == "pi"));
+        assert!(matches!(&tokens[3], PrintToken::Variable(v) if v == "half"));
+        assert!(asm.exit);
+        assert_eq!(asm.exit_code, 3);
+    }
+
+    #[test]
+    fn if_else_chains_nest() {
+        let asm = parse(
+            "var n: i32 = 5;
+             var big: i64 = 1;
+             if n < 0 {
+                 print \"negative\";
+             } else if unsigned big >= 18446744073709551615 {
+                 if n != -1 { print \"max\"; }
+             } else {
+                 print \"other\";
+             }",
+        )
+        .unwrap();
+        let [Statement::If {
+            condition,
+            then,
+            otherwise,
+        }] = asm.statements.as_slice()
+        else {
+            panic!("expected one if");
+        };
+        assert_eq!(*condition, Condition::new("n", Compare::Lt, 0));
+        assert_eq!(then.len(), 1);
+        let [Statement::If {
+            condition,
+            then,
+            otherwise,
+        }] = otherwise.as_slice()
+        else {
+            panic!("expected else if");
+        };
+        assert_eq!(
+            *condition,
+            Condition::new("big", Compare::Ge, -1).unsigned()
+        );
+        assert!(
+            matches!(&then[0], Statement::If { condition, .. } if condition.right == Value::Const(-1))
+        );
+        assert_eq!(otherwise.len(), 1);
+    }
+
+    #[test]
+    fn loops_take_break_and_continue() {
+        let asm = parse(
+            "var i: i32 = 0;
+             var k: i64 = 0;
+             for i in 10..-10 step -2 {
+                 while unsigned k < 4294967296 {
+                     if k == 3 { break; }
+                     continue;
+                 }
+             }
+             for k in i..5000000000 { break; }",
+        )
+        .unwrap();
+        let [Statement::For {
+            var,
+            from,
+            to,
+            step: -2,
+            body,
+        }, Statement::For {
+            step: 1,
+            body: last,
+            ..
+        }] = asm.statements.as_slice()
+        else {
+            panic!("expected two for loops");
+        };
+        assert_eq!(
+            (var.as_str(), from, to),
+            ("i", &Value::Const(10), &Value::Const(-10))
+        );
+        assert!(matches!(last.as_slice(), [Statement::Break]));
+        let [Statement::While { condition, body }] = body.as_slice() else {
+            panic!("expected a while loop");
+        };
+        assert_eq!(
+            *condition,
+            Condition::new("k", Compare::Lt, 1i64 << 32).unsigned()
+        );
+        assert!(matches!(
+            body.as_slice(),
+            [Statement::If { then, .. }, Statement::Continue]
+                if matches!(then.as_slice(), [Statement::Break])
+        ));
+    }
+
+    #[test]
+    fn assignments_become_set_add_and_sub() {
+        let asm = parse(
+            "var n: i32 = 0;
+             var m: i32 = 2;
+             var k: i64 = 0;
+             n = -5;
+             n += m;
+             n -= 1;
+             n = n + 1;
+             n = 3 + n;
+             n = n - m;
+             k = m + 7;
+             k = n - 2147483648;
+             while n < 10 { n += 1; }",
+        )
+        .unwrap();
+        let flat: Vec<(&str, &str, &Value)> = asm
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Set { var, value } => Some(("set", var.as_str(), value)),
+                Statement::Add { var, value } => Some(("add", var.as_str(), value)),
+                Statement::Sub { var, value } => Some(("sub", var.as_str(), value)),
+                _ => None,
+            })
+            .collect();
+        let m = Value::from("m");
+        assert_eq!(
+            flat,
+            [
+                ("set", "n", &Value::Const(-5)),
+                ("add", "n", &m),
+                ("sub", "n", &Value::Const(1)),
+                ("add", "n", &Value::Const(1)),
+                ("add", "n", &Value::Const(3)),
+                ("sub", "n", &m),
+                ("set", "k", &m),
+                ("add", "k", &Value::Const(7)),
+                ("set", "k", &Value::from("n")),
+                ("sub", "k", &Value::Const(2147483648)),
+            ]
+        );
+        assert!(matches!(
+            asm.statements.last(),
+            Some(Statement::While { body, .. })
+                if matches!(body.as_slice(), [Statement::Add { var, .. }] if var == "n")
+        ));
+    }
+
+    #[test]
+    fn mistakes_are_all_reported() {
+        let messages = |source| -> Vec<String> {
+            parse(source)
+                .err()
+                .unwrap()
+                .iter()
+                .map(|d| d.to_string())
+                .collect()
+        };
+        assert_eq!(
+            messages(
+                "var n: i32 = 3000000000;
+var s: str = \"x\";
+var n: i32 = 1;
+var x: f64 = \"2\";
+print n:2, \"a\" \"b\";
+exit 256;
+prnt n;
+var _start: i32 = 0;"
+            ),
+            [
+                "1:14: value is out of range for i32",
+                "2:8: unknown type `str`; expected string, i32, i64, f32 or f64",
+                "4:14: expected an f64 value, found a string",
+                "5:7: `n` is i32; only f32 and f64 variables take a precision",
+                "6:6: exit status has to be from 0 to 255",
+                "7:1: expected `var`, `print`, `if`, `while`, `for`, `break`, `continue`, `exit` or an assignment, found `prnt`",
+                "8:5: `_start` can't be used as a variable name",
+            ]
+        );
+        assert_eq!(
+            messages("var n: i32 = 1;\nvar n: i64 = 2;\nprint n n;\nexit;\nprint n;"),
+            [
+                "2:5: `n` is already declared",
+                "3:9: expected `,` or `;`, found `n`",
+                "5:1: unreachable statement; `exit` has to come last",
+            ]
+        );
+        assert_eq!(
+            messages("var"),
+            ["1:4: expected a variable name, found the end of the file"]
+        );
+        assert_eq!(
+            messages(
+                "var x: f64 = 1;
+if x < 1 { print \"a\"; } else { print \"b\"; }
+if y == 2 {}
+if 1 == 2 {
+    var z: i32 = 1;
+    print nope;
+    exit;
+}
+if 1 < -18446744073709551615 {}"
+            ),
+            [
+                "2:4: `x` is f64; only i32 and i64 variables can be compared",
+                "3:4: undefined variable `y`",
+                "5:5: `var` can't be inside a block",
+                "6:11: undefined variable `nope`",
+                "7:5: `exit` can't be inside a block",
+                "9:8: constant is out of range",
+            ]
+        );
+        assert_eq!(
+            messages(
+                "var i: i32 = 0;
+var x: f64 = 0;
+break;
+if i == 0 { continue; }
+for x in 0..1 {}
+for i in 3000000000..0 {}
+for i in 0..10 step 0 {}
+for i in 0..10 step -3000000000 {}
+for i in 2147483640..3000000000 step 4 {}
+for i 0..10 {}
+while i < 3 { for i in 0..1 { break; } break }"
+            ),
+            [
+                "3:1: `break` has to be inside a loop",
+                "4:13: `continue` has to be inside a loop",
+                "5:5: `x` is f64; loop variables have to be i32 or i64",
+                "6:10: start is out of range for `i`, an i32",
+                "7:21: step can't be 0",
+                "8:21: step is out of range for `i`, an i32",
+                "9:22: end is out of range for `i`, an i32",
+                "10:7: expected `in`, found a number",
+                "11:46: expected `;`, found `}`",
+            ]
+        );
+        assert_eq!(
+            messages(
+                "var n: i32 = 0;
+var w: i64 = 0;
+var x: f64 = 0;
+x = 1;
+n = w;
+n += 3000000000;
+n = 1 - n;
+n = w + 1;
+nope = 2;
+n = n 2;
+n -= x;"
+            ),
+            [
+                "4:1: `x` is f64; only i32 and i64 variables can be assigned",
+                "5:5: value is out of range for `n`, an i32",
+                "6:6: value is out of range for `n`, an i32",
+                "7:9: `n` can't be subtracted from another value while it's being set",
+                "8:5: value is out of range for `n`, an i32",
+                "9:1: undefined variable `nope`",
+                "10:7: expected `;`, found a number",
+                "11:6: `x` is f64; only i32 and i64 variables can be subtracted",
+            ]
+        );
+    }
+}
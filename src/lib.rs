@@ -0,0 +1,28 @@
+// describe a program as a CoreAsm (in Rust, or as source text for lang),
+// pick a Target, and get assembly text (maker), machine code (encode), an
+// executable written in process (maker::executable) or one built with the
+// system toolchain (build)
+pub mod arch;
+pub mod build;
+pub mod builder;
+pub mod coreasm;
+pub mod elf;
+pub mod emit;
+pub mod encode;
+pub mod error;
+pub mod ir;
+pub mod lang;
+#[cfg(feature = "serde")]
+pub mod load;
+pub mod maker;
+mod runtime;
+
+pub use arch::{Arch, Bit, Target, OS};
+pub use builder::Builder;
+pub use coreasm::{
+    Compare, Condition, CoreAsm, Print, PrintToken, Statement, Types, Value, VarValue,
+};
+#[cfg(feature = "serde")]
+pub use error::LoadError;
+pub use error::{BuildError, MakeError};
+pub use maker::maker;
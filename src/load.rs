@@ -0,0 +1,189 @@
+// programs kept as data, in the shape serde derives for CoreAsm:
+//
+//   {
+//     "data": {"variables": [["name", {"type": "string", "value": {"string": "joy"}}]]},
+//     "prints": [{"tokens": [{"text": "Hello "}, {"variable": "name"}, "newline"]}],
+//     "statements": [{"if": {
+//       "condition": {"left": {"var": "n"}, "compare": "lt", "right": {"const": 10}},
+//       "then": [{"print": {"tokens": [{"text": "small"}]}}],
+//       "otherwise": []
+//     }}],
+//     "exit": true
+//   }
+//
+// or the same in TOML. whatever is loaded has been validated, so maker gets
+// a program it can lower
+use crate::{
+    arch::Target, coreasm::CoreAsm, emit::Syntax, error::LoadError, maker::maker_with_syntax,
+};
+use std::{fs, path::Path};
+
+pub fn from_json(text: &str) -> Result<CoreAsm, LoadError> {
+    let core_asm: CoreAsm = serde_json::from_str(text)?;
+    core_asm.validate()?;
+    Ok(core_asm)
+}
+
+pub fn from_toml(text: &str) -> Result<CoreAsm, LoadError> {
+    let core_asm: CoreAsm = toml::from_str(text)?;
+    core_asm.validate()?;
+    Ok(core_asm)
+}
+
+// reads a .json or .toml file
+pub fn from_path(path: &Path) -> Result<CoreAsm, LoadError> {
+    let load = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => from_json,
+        Some("toml") => from_toml,
+        _ => return Err(LoadError::UnknownFormat(path.display().to_string())),
+    };
+    load(&fs::read_to_string(path)?)
+}
+
+// from_path, then straight on to assembly in the target's default syntax
+pub fn maker(path: &Path, target: Target) -> Result<String, LoadError> {
+    let core_asm = from_path(path)?;
+    Ok(maker_with_syntax(
+        &core_asm,
+        target,
+        Syntax::default_for(&target),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arch::{Arch, Bit, OS},
+        coreasm::{Compare, Condition, PrintToken, Statement, Types, Value},
+        error::MakeError,
+    };
+
+    const TOML: &str = r#"
+exit = true
+exit_code = 3
+
+[data]
+variables = [
+    ["name", { type = "string", value = { string = "joy" } }],
+    ["pi", { type = "f64", value = { f64 = 3.14159 } }],
+]
+
+[[prints]]
+tokens = [{ text = "Hello " }, { variable = "name" }, "newline"]
+
+[[prints]]
+tokens = [{ float = { var = "pi", precision = 2 } }, "newline"]
+"#;
+
+    #[test]
+    fn json_and_toml_load_the_same_program() {
+        let asm = from_toml(TOML).unwrap();
+        assert_eq!(asm.exit_code, 3);
+        assert!(matches!(
+            &asm.prints[1].tokens[0],
+            PrintToken::Float { var, precision: 2 } if var == "pi"
+        ));
+        let json = serde_json::to_string(&asm).unwrap();
+        let again = from_json(&json).unwrap();
+        assert_eq!(serde_json::to_string(&again).unwrap(), json);
+        let target = Target::new(Bit::X64, Arch::X86, OS::Linux);
+        assert_eq!(
+            crate::maker::maker(&again, target).unwrap(),
+            crate::maker::maker(&asm, target).unwrap()
+        );
+    }
+
+    #[test]
+    fn statements_load_as_tagged_maps() {
+        let json = r#"{
+            "data": {"variables": [["n", {"type": "i64", "value": {"i64": 7}}]]},
+            "statements": [{"if": {
+                "condition": {"left": {"var": "n"}, "compare": "ge", "right": {"const": 5}},
+                "then": [{"print": {"tokens": [{"variable": "n"}]}}]
+            }}]
+        }"#;
+        let asm = from_json(json).unwrap();
+        assert!(matches!(
+            asm.statements.as_slice(),
+            [Statement::If { condition, otherwise, .. }]
+                if *condition == Condition::new("n", Compare::Ge, 5) && otherwise.is_empty()
+        ));
+        let counted = r#"{
+            "data": {"variables": [["i", {"type": "i32", "value": {"i32": 0}}]]},
+            "statements": [{"for": {
+                "var": "i", "from": {"const": 0}, "to": {"const": 3},
+                "body": [{"while": {
+                    "condition": {"left": {"var": "i"}, "compare": "eq", "right": {"const": 1}},
+                    "body": ["break"]
+                }}, "continue"]
+            }}]
+        }"#;
+        let asm = from_json(counted).unwrap();
+        assert!(matches!(
+            asm.statements.as_slice(),
+            [Statement::For { step: 1, body, .. }]
+                if matches!(body.as_slice(), [Statement::While { .. }, Statement::Continue])
+        ));
+        let summed = r#"{
+            "data": {"variables": [["n", {"type": "i32", "value": {"i32": 0}}]]},
+            "statements": [{"set": {"var": "n", "value": {"const": 5}}}, {"while": {
+                "condition": {"left": {"var": "n"}, "compare": "gt", "right": {"const": 0}},
+                "body": [{"sub": {"var": "n", "value": {"const": 2}}}]
+            }}, {"add": {"var": "n", "value": {"var": "n"}}}]
+        }"#;
+        let asm = from_json(summed).unwrap();
+        assert!(matches!(
+            asm.statements.as_slice(),
+            [Statement::Set { .. }, Statement::While { body, .. }, Statement::Add { value, .. }]
+                if matches!(body.as_slice(), [Statement::Sub { .. }]) && *value == Value::from("n")
+        ));
+        let too_big = summed.replace(r#"{"const": 5}"#, r#"{"const": 5000000000}"#);
+        assert!(matches!(
+            from_json(&too_big),
+            Err(LoadError::Make(MakeError::TypeMismatch { var, .. })) if var == "n"
+        ));
+        let unknown = json.replace(r#"{"var": "n"}"#, r#"{"var": "m"}"#);
+        assert!(matches!(
+            from_json(&unknown),
+            Err(LoadError::Make(MakeError::UndefinedVariable(name))) if name == "m"
+        ));
+    }
+
+    #[test]
+    fn loaded_programs_are_validated() {
+        // left-out fields take their defaults
+        let asm = from_json(r#"{"prints": [{"tokens": [{"text": "hi"}]}]}"#).unwrap();
+        assert!(!asm.exit && asm.data.variables.is_empty());
+
+        let declared = r#"{"data": {"variables": [["n", {"type": "i32", "value": {"i64": 1}}]]}}"#;
+        assert!(matches!(
+            from_json(declared),
+            Err(LoadError::Make(MakeError::TypeMismatch {
+                expected: Types::I32,
+                found: Types::I64,
+                ..
+            }))
+        ));
+        let printed = r#"{"prints": [{"tokens": [{"variable": "who"}]}]}"#;
+        assert!(matches!(
+            from_json(printed),
+            Err(LoadError::Make(MakeError::UndefinedVariable(name))) if name == "who"
+        ));
+        let named =
+            r#"{"data": {"variables": [["two words", {"type": "i32", "value": {"i32": 1}}]]}}"#;
+        assert!(matches!(
+            from_json(named),
+            Err(LoadError::Make(MakeError::BadName(_)))
+        ));
+        assert!(matches!(
+            from_json(r#"{"exit": "yes"}"#),
+            Err(LoadError::Json(_))
+        ));
+        assert!(matches!(from_toml("exit = "), Err(LoadError::Toml(_))));
+        assert!(matches!(
+            from_path(Path::new("program.yaml")),
+            Err(LoadError::UnknownFormat(_))
+        ));
+    }
+}
@@ -1,32 +1,305 @@
-use arch::Target;
-use coreasm::{CoreAsm, Print, PrintToken};
-use maker::maker;
+use coreasm::{build, elf, emit::Syntax, lang, maker, Arch, Bit, CoreAsm, MakeError, Target, OS};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
 
-mod arch;
-mod coreasm;
-mod maker;
+const USAGE: &str = "usage: coreasm <emit|build|run> <program> [options]
+
+  <program> is a .casm source file, or .json/.toml with the serde feature
+
+  emit    print the assembly (or write it to -o)
+  build   assemble and link an executable
+  run     build, then run it
+
+options:
+  --target <triple>   x86_64-linux, i686-linux, aarch64-linux or armv7-linux;
+                      defaults to this machine
+  --syntax <syntax>   nasm, intel or att (gnu as); defaults to nasm on x86 and gnu as on arm
+  --direct            write the executable in process instead of calling an assembler and linker
+  -o <path>           output file; build and run default to the program without
+                      its extension, or <program>.out when it has none";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Emit,
+    Build,
+    Run,
+}
+
+struct Options {
+    mode: Mode,
+    input: PathBuf,
+    target: Target,
+    syntax: Option<Syntax>,
+    direct: bool,
+    output: Option<PathBuf>,
+}
+
+fn parse_syntax(name: &str) -> Result<Syntax, String> {
+    match name {
+        "nasm" => Ok(Syntax::Nasm),
+        "intel" => Ok(Syntax::GasIntel),
+        "att" => Ok(Syntax::GasAtt),
+        _ => Err(format!("unknown syntax: {}", name)),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mode = match args.first().map(String::as_str) {
+        Some("emit") => Mode::Emit,
+        Some("build") => Mode::Build,
+        Some("run") => Mode::Run,
+        Some(other) => return Err(format!("unknown command: {}", other)),
+        None => return Err("no command given".to_string()),
+    };
+    let mut input = None;
+    let mut target = Target::host().unwrap_or(Target::new(Bit::X64, Arch::X86, OS::Linux));
+    let mut syntax = None;
+    let mut direct = false;
+    let mut output = None;
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--target" => target = value()?.parse().map_err(|e: MakeError| e.to_string())?,
+            "--syntax" => syntax = Some(parse_syntax(value()?)?),
+            "--direct" => direct = true,
+            "-o" => output = Some(PathBuf::from(value()?)),
+            flag if flag.starts_with('-') => return Err(format!("unknown option: {}", flag)),
+            path if input.is_none() => input = Some(PathBuf::from(path)),
+            extra => return Err(format!("unexpected argument: {}", extra)),
+        }
+    }
+    Ok(Options {
+        mode,
+        input: input.ok_or("no program file given")?,
+        target,
+        syntax,
+        direct,
+        output,
+    })
+}
+
+// a program in the source language, or with the serde feature a .json or
+// .toml one
+fn read_program(path: &Path) -> Result<CoreAsm, String> {
+    if matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("json" | "toml")
+    ) {
+        #[cfg(feature = "serde")]
+        return coreasm::load::from_path(path).map_err(|e| format!("{}: {}", path.display(), e));
+        #[cfg(not(feature = "serde"))]
+        return Err(format!(
+            "{}: json and toml programs need the serde feature",
+            path.display()
+        ));
+    }
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    lang::parse(&text).map_err(|diagnostics| {
+        let path = path.display().to_string();
+        let rendered: Vec<String> = diagnostics.iter().map(|d| d.render(&text, &path)).collect();
+        // main puts the first "error: " in front
+        rendered.join("\n\nerror: ")
+    })
+}
+
+// whether two paths name one file, also when they're spelled differently
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b || matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+// the build writes these, so none may be the program it was given
+fn keep_input(input: &Path, written: &[PathBuf]) -> Result<(), String> {
+    match written.iter().find(|path| same_file(input, path)) {
+        Some(path) => Err(format!(
+            "{}: would be overwritten by the build, pick another -o",
+            path.display()
+        )),
+        None => Ok(()),
+    }
+}
+
+// emit's output; a reader that stops early (`coreasm emit prog.casm | head`)
+// closes the pipe, which isn't an error
+fn write_code(out: &mut impl Write, code: &str) -> Result<(), String> {
+    match writeln!(out, "{}", code).and_then(|()| out.flush()) {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+// builds go next to the input: prog.casm builds prog, and a program without
+// an extension builds prog.out rather than itself
+fn default_output(input: &Path) -> PathBuf {
+    let out = input.with_extension("");
+    if out == input {
+        input.with_extension("out")
+    } else {
+        out
+    }
+}
+
+fn run(options: Options) -> Result<i32, String> {
+    let asm = read_program(&options.input)?;
+    let syntax = options
+        .syntax
+        .unwrap_or_else(|| Syntax::default_for(&options.target));
+
+    if options.mode == Mode::Emit {
+        let code =
+            maker::maker_with_syntax(&asm, options.target, syntax).map_err(|e| e.to_string())?;
+        match &options.output {
+            Some(path) => {
+                keep_input(&options.input, std::slice::from_ref(path))?;
+                fs::write(path, code).map_err(|e| e.to_string())?
+            }
+            None => write_code(&mut io::stdout().lock(), &code)?,
+        }
+        return Ok(0);
+    }
+
+    let out = options
+        .output
+        .clone()
+        .unwrap_or_else(|| default_output(&options.input));
+    let exe = if options.direct {
+        keep_input(&options.input, std::slice::from_ref(&out))?;
+        let image = maker::executable(&asm, options.target).map_err(|e| e.to_string())?;
+        elf::write(&out, &image).map_err(|e| e.to_string())?;
+        out
+    } else {
+        let [source, object] =
+            build::intermediates(&options.target, syntax, &out).map_err(|e| e.to_string())?;
+        keep_input(&options.input, &[source, object, out.clone()])?;
+        build::build_with_syntax(&asm, options.target, syntax, &out).map_err(|e| e.to_string())?
+    };
+    if options.mode == Mode::Build {
+        return Ok(0);
+    }
+    // a bare file name would be looked up in PATH
+    let exe = if exe.parent() == Some(Path::new("")) {
+        Path::new(".").join(exe)
+    } else {
+        exe
+    };
+    let status = Command::new(&exe)
+        .status()
+        .map_err(|e| format!("{}: {}", exe.display(), e))?;
+    Ok(status.code().unwrap_or(1))
+}
 
 fn main() {
-    // Initialize CoreAsm and add variables
-    let mut asm = CoreAsm::new();
-    // add variables to data section
-    asm.data.mkvar(
-        "name".to_string(),
-        coreasm::Types::String,
-        coreasm::VarValue::String("joy".to_string()),
-    );
-    //print
-    let mut print = Print::new();
-    print.tokens.push(PrintToken::Text("Hello ".to_string()));
-    print.tokens.push(PrintToken::Variable("name".to_string()));
-    print.tokens.push(PrintToken::Text("!".to_string()));
-    print.tokens.push(PrintToken::Newline);
-    asm.prints.push(print);
-    //generate assembly code
-    asm.exit(true);
-    let asmcode = maker(
-        &asm,
-        Target::new(arch::Bit::X64, arch::Arch::X86, arch::OS::Windows),
-    );
-    println!("{}", asmcode);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    match run(options) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn options_are_parsed() {
+        let options = parse_args(&args("build hello.casm --target i686-linux -o hi --direct"))
+            .unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(options.mode, Mode::Build);
+        assert_eq!(options.input, PathBuf::from("hello.casm"));
+        assert_eq!(
+            (options.target.bit, options.target.arch),
+            (Bit::X32, Arch::X86)
+        );
+        assert!(options.direct);
+        assert_eq!(options.output, Some(PathBuf::from("hi")));
+        assert!(parse_args(&args("emit")).is_err());
+        assert!(parse_args(&args("emit a --syntax masm")).is_err());
+        assert!(parse_args(&args("emit a -o")).is_err());
+    }
+
+    #[test]
+    fn closed_pipes_end_emit_quietly() {
+        struct Failing(io::ErrorKind);
+        impl Write for Failing {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(self.0.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        assert_eq!(
+            write_code(&mut Failing(io::ErrorKind::BrokenPipe), "nop"),
+            Ok(())
+        );
+        assert!(write_code(&mut Failing(io::ErrorKind::StorageFull), "nop").is_err());
+        let mut written = Vec::new();
+        write_code(&mut written, "nop").unwrap();
+        assert_eq!(written, b"nop\n");
+    }
+
+    #[test]
+    fn builds_leave_the_program_alone() {
+        let dir = std::env::temp_dir().join(format!("coreasm-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("prog.asm");
+        let source = "var n: i32 = 1;\nprint n, \"\\n\";\nexit 0;\n";
+        fs::write(&input, source).unwrap();
+        // nasm's source would be prog.asm; refused before nasm is needed
+        let line = format!(
+            "build {} --target x86_64-linux --syntax nasm",
+            input.display()
+        );
+        let error = run(parse_args(&args(&line)).unwrap()).unwrap_err();
+        let kept = fs::read_to_string(&input).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(error.contains("would be overwritten"), "{}", error);
+        assert_eq!(kept, source);
+    }
+
+    #[test]
+    fn programs_without_an_extension_build_beside_themselves() {
+        assert_eq!(
+            default_output(Path::new("a/prog.casm")),
+            Path::new("a/prog")
+        );
+        assert_eq!(default_output(Path::new("a/prog")), Path::new("a/prog.out"));
+        let dir = std::env::temp_dir().join(format!("coreasm-noext-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("prog");
+        let source = "var n: i32 = 1;\nprint n, \"\\n\";\nexit 0;\n";
+        fs::write(&input, source).unwrap();
+        let line = format!("build {} --target x86_64-linux --direct", input.display());
+        let options = parse_args(&args(&line)).unwrap();
+        assert_eq!(options.output, None);
+        let built = run(options);
+        let written = dir.join("prog.out").exists();
+        // and an explicit -o naming the program is refused
+        let line = format!("build {0} --direct -o {0}", input.display());
+        let refused = run(parse_args(&args(&line)).unwrap());
+        let kept = fs::read_to_string(&input).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(built, Ok(0));
+        assert!(written);
+        assert!(refused.is_err());
+        assert_eq!(kept, source);
+    }
 }
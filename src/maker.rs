@@ -1,196 +1,1457 @@
 use crate::{
-    arch::Target,
-    coreasm::{self, CoreAsm, PrintToken, VarValue},
+    arch::{Arch, Bit, RegRole, Register, SyscallAbi, Target, Width},
+    coreasm::{
+        self, Compare, Condition, CoreAsm, Print, PrintToken, Statement, Types, Value, VarValue,
+    },
+    elf,
+    emit::{self, Syntax},
+    encode,
+    error::MakeError,
+    ir::{Cond, DataValue, Instr, Mem, Operand, Program},
+    runtime,
 };
 use std::collections::HashMap;
 
-pub fn maker(core_asm: &CoreAsm, arch: Target) -> String {
-    let arch_map: HashMap<String, String> = arch.regs().unwrap();
-    let mut asm_code = String::new();
-    let mut data_section = String::new();
-    let mut entry_section = String::new();
-    let mut cvs: HashMap<String, String> = HashMap::new();
-    let mut str_index = 555;
-    data_section.push_str("jnl: db 0x0A\n");
+// digits after the point when a float is printed through PrintToken::Variable
+pub const DEFAULT_PRECISION: u32 = 6;
 
-    for (name, var) in &core_asm.data.variables {
-        let var_type = match &var.var_type {
-            crate::coreasm::Types::I32 => "dd",
-            crate::coreasm::Types::I64 => "dq",
-            crate::coreasm::Types::F32 => "dd",
-            crate::coreasm::Types::F64 => "dq",
-            crate::coreasm::Types::String => "db",
-        };
-        let value = match &var.value {
-            VarValue::I32(i) => i.to_string(),
-            VarValue::I64(i) => i.to_string(),
-            VarValue::F32(f) => f.to_string(),
-            VarValue::F64(f) => f.to_string(),
-            VarValue::String(s) => format!("\"{}\"", s),
-        };
-        data_section.push_str(&format!("{} {} {}\n", name, var_type, value));
-    }
-
-    let mut pindex = 0;
-    for ps in core_asm.prints.iter() {
-        pindex += 1;
-        for token in ps.tokens.iter() {
-            match token {
-                PrintToken::Text(text) => {
-                    let var_name = format!("str_{}", str_index + pindex);
-                    str_index += 1;
-                    cvs.insert(var_name.clone(), text.clone());
-                    data_section.push_str(&format!("{} db \"{}\"\n", var_name, text));
-                    let string_register = arch_map.get("string").unwrap();
-                    let string_length = text.len().to_string();
-
-                    entry_section.push_str(&format!(
-                        "{} {}, {}\n",
-                        arch_map.get("mov").unwrap(),
-                        string_register,
-                        var_name
-                    ));
-
-                    entry_section.push_str(&format!(
-                        "{} {}, {}\n",
-                        arch_map.get("mov").unwrap(),
-                        arch_map.get("Dest Index Register (i64)").unwrap(),
-                        arch_map.get("Syscall Write").unwrap()
-                    ));
-
-                    entry_section.push_str(&format!(
-                        "{} {}, {}\n",
-                        arch_map.get("mov").unwrap(),
-                        arch_map.get("rdx").unwrap(),
-                        string_length
-                    ));
-
-                    entry_section.push_str(&format!(
-                        "{} {}, {}\n",
-                        arch_map.get("mov").unwrap(),
-                        arch_map.get("rax").unwrap(),
-                        arch_map.get("Syscall Write").unwrap()
-                    ));
-
-                    entry_section.push_str(&format!("{}\n", arch_map.get("Syscall").unwrap()));
+// the label a user variable is emitted under. names are passed to the
+// assembler, where registers and keywords such as `rax`, `sp` or `offset`
+// would be read as something else, so every one gets the same prefix. the
+// runtime's symbols start with underscores and can't be hit by it
+fn symbol(name: &str) -> String {
+    format!("cv_{name}")
+}
+
+// interns print text so identical literals share one data item. string
+// variables are seeded under their own label; other text gets a label
+// numbered in first-use order and prefixed like the runtime's symbols, so it
+// can't clash with user variables
+#[derive(Default)]
+struct StrPool {
+    labels: HashMap<Vec<u8>, String>,
+    next: usize,
+}
+
+impl StrPool {
+    fn seed(&mut self, bytes: &[u8], label: &str) {
+        self.labels
+            .entry(bytes.to_vec())
+            .or_insert_with(|| label.to_string());
+    }
+
+    fn intern(&mut self, program: &mut Program, bytes: &[u8]) -> String {
+        if let Some(label) = self.labels.get(bytes) {
+            return label.clone();
+        }
+        let label = format!("__coreasm_str_{}", self.next);
+        self.next += 1;
+        program.data(&label, DataValue::Bytes(bytes.to_vec()));
+        self.labels.insert(bytes.to_vec(), label.clone());
+        label
+    }
+}
+
+// a Print once its variables are resolved: runs of text known at compile
+// time (literals, newlines, string variables) and values formatted at run time
+enum Piece<'a> {
+    Text(Vec<u8>),
+    Int(&'a str, &'a VarValue),
+    Float(&'a str, &'a VarValue, u32),
+}
+
+fn pieces<'a>(core_asm: &'a CoreAsm, print: &'a Print) -> Result<Vec<Piece<'a>>, MakeError> {
+    let mut pieces = Vec::new();
+    let mut text = Vec::new();
+    for token in print.tokens.iter() {
+        let piece = match token {
+            PrintToken::Text(t) => {
+                text.extend_from_slice(t.as_bytes());
+                continue;
+            }
+            PrintToken::Newline => {
+                text.push(b'\n');
+                continue;
+            }
+            PrintToken::Variable(var_name) => {
+                let var = core_asm
+                    .data
+                    .get(var_name)
+                    .ok_or_else(|| MakeError::UndefinedVariable(var_name.clone()))?;
+                match &var.value {
+                    VarValue::String(s) => {
+                        text.extend_from_slice(s.as_bytes());
+                        continue;
+                    }
+                    VarValue::I32(_) | VarValue::I64(_) => Piece::Int(var_name, &var.value),
+                    VarValue::F32(_) | VarValue::F64(_) => {
+                        Piece::Float(var_name, &var.value, DEFAULT_PRECISION)
+                    }
                 }
-                PrintToken::Variable(var_name) => {
-                    if let Some(var) = core_asm.data.variables.get(var_name) {
-                        let var_type = &var.var_type;
-                        let register = match var_type {
-                            coreasm::Types::I32 => arch_map.get("i32").unwrap(),
-                            coreasm::Types::I64 => arch_map.get("i64").unwrap(),
-                            coreasm::Types::F32 => arch_map.get("f32").unwrap(),
-                            coreasm::Types::F64 => arch_map.get("f64").unwrap(),
-                            coreasm::Types::String => arch_map.get("string").unwrap(),
-                        };
-                        let value = match &var.value {
-                            VarValue::I32(i) => i.to_string(),
-                            VarValue::I64(i) => i.to_string(),
-                            VarValue::F32(f) => f.to_string(),
-                            VarValue::F64(f) => f.to_string(),
-                            VarValue::String(s) => format!("\"{}\"", s),
-                        };
-
-                        entry_section.push_str(&format!(
-                            "{} {}, {}\n",
-                            arch_map.get("mov").unwrap(),
-                            register,
-                            var_name
-                        ));
-
-                        entry_section.push_str(&format!(
-                            "{} {}, {}\n",
-                            arch_map.get("mov").unwrap(),
-                            arch_map.get("Dest Index Register (i64)").unwrap(),
-                            arch_map.get("Syscall Write").unwrap()
-                        ));
-
-                        entry_section.push_str(&format!(
-                            "{} {}, {}\n",
-                            arch_map.get("mov").unwrap(),
-                            arch_map.get("rdx").unwrap(),
-                            (value.len() - 2).to_string()
-                        ));
-
-                        entry_section.push_str(&format!(
-                            "{} {}, {}\n",
-                            arch_map.get("mov").unwrap(),
-                            arch_map.get("rax").unwrap(),
-                            arch_map.get("Syscall Write").unwrap()
-                        ));
-
-                        entry_section.push_str(&format!("{}\n", arch_map.get("Syscall").unwrap()));
+            }
+            PrintToken::Float { var, precision } => {
+                let value = &core_asm
+                    .data
+                    .get(var)
+                    .ok_or_else(|| MakeError::UndefinedVariable(var.clone()))?
+                    .value;
+                if !matches!(value, VarValue::F32(_) | VarValue::F64(_)) {
+                    return Err(MakeError::TypeMismatch {
+                        var: var.clone(),
+                        expected: coreasm::Types::F64,
+                        found: value.types(),
+                    });
+                }
+                Piece::Float(var, value, *precision)
+            }
+        };
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(&mut text)));
+        }
+        pieces.push(piece);
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+// loads the syscall number and arguments into the target's abi registers and traps
+fn syscall(
+    program: &mut Program,
+    abi: &SyscallAbi,
+    arch: &Target,
+    nr: u32,
+    args: Vec<Operand>,
+) -> Result<(), MakeError> {
+    if args.len() > RegRole::ARGS.len() {
+        return Err(MakeError::MissingRegister {
+            target: arch.to_string(),
+            role: format!("Arg{}", RegRole::ARGS.len()),
+        });
+    }
+    for (role, value) in RegRole::ARGS.iter().zip(args) {
+        program.push(Instr::Mov {
+            dst: arch.reg(*role)?,
+            src: value,
+        });
+    }
+    program.push(Instr::Mov {
+        dst: arch.reg(RegRole::SyscallNr)?,
+        src: Operand::Imm(nr as i64),
+    });
+    program.push(Instr::Syscall(abi.trap));
+    Ok(())
+}
+
+// write(1, label, len)
+fn write(
+    program: &mut Program,
+    abi: &SyscallAbi,
+    arch: &Target,
+    label: &str,
+    len: usize,
+) -> Result<(), MakeError> {
+    syscall(
+        program,
+        abi,
+        arch,
+        abi.write,
+        vec![
+            Operand::Imm(1),
+            Operand::Label(label.to_string()),
+            Operand::Imm(len as i64),
+        ],
+    )
+}
+
+// loads an integer variable into the formatter's argument registers and calls it
+fn fmt_int(
+    program: &mut Program,
+    arch: &Target,
+    name: &str,
+    value: &VarValue,
+) -> Result<(), MakeError> {
+    let lo = arch.reg(RegRole::Arg0)?;
+    let hi = arch.reg(RegRole::Arg1)?;
+    let pair = arch.bit == Bit::X32;
+    let base = if pair { hi } else { lo };
+    program.push(Instr::Mov {
+        dst: base,
+        src: Operand::Label(symbol(name)),
+    });
+    let (width, routine) = match value {
+        VarValue::I64(_) if !pair => (Width::W64, runtime::FMT_I64),
+        VarValue::I64(_) => (Width::W32, runtime::FMT_I64),
+        _ => (Width::W32, runtime::FMT_I32),
+    };
+    program.push(Instr::Load {
+        dst: lo,
+        src: Mem { base, offset: 0 },
+        width,
+    });
+    if pair && routine == runtime::FMT_I64 {
+        program.push(Instr::Load {
+            dst: hi,
+            src: Mem { base, offset: 4 },
+            width,
+        });
+    }
+    program.push(Instr::Call(routine.to_string()));
+    Ok(())
+}
+
+// loads a float variable as a double and calls the float formatter
+fn fmt_float(
+    program: &mut Program,
+    arch: &Target,
+    name: &str,
+    value: &VarValue,
+    precision: u32,
+) -> Result<(), MakeError> {
+    if precision > runtime::MAX_PRECISION {
+        return Err(MakeError::BadPrecision {
+            var: name.to_string(),
+            precision,
+        });
+    }
+    let base = arch.reg(RegRole::Arg1)?;
+    let x = arch.reg(RegRole::FloatScratch)?;
+    program.push(Instr::Mov {
+        dst: base,
+        src: Operand::Label(symbol(name)),
+    });
+    let width = match value {
+        VarValue::F32(_) => Width::W32,
+        _ => Width::W64,
+    };
+    program.push(Instr::Load {
+        dst: x,
+        src: Mem { base, offset: 0 },
+        width,
+    });
+    if width == Width::W32 {
+        program.push(Instr::FWiden(x));
+    }
+    program.push(Instr::Mov {
+        dst: arch.reg(RegRole::Arg3)?,
+        src: Operand::Imm(10i64.pow(precision)),
+    });
+    program.push(Instr::Call(runtime::FMT_F64.to_string()));
+    Ok(())
+}
+
+// formats a print with run-time values into a stack buffer sized for the
+// worst case, then writes the buffer with a single syscall
+fn buffered(
+    program: &mut Program,
+    pool: &mut StrPool,
+    needs: &mut runtime::Needs,
+    abi: &SyscallAbi,
+    arch: &Target,
+    pieces: &[Piece],
+) -> Result<(), MakeError> {
+    let sp = arch.reg(RegRole::StackPointer)?;
+    let buf = arch.reg(RegRole::Arg1)?;
+    let cur = arch.reg(RegRole::Arg2)?;
+    let size: usize = pieces
+        .iter()
+        .map(|piece| match piece {
+            Piece::Text(bytes) => bytes.len(),
+            Piece::Int(..) => runtime::INT_CHARS,
+            Piece::Float(..) => runtime::FLOAT_CHARS,
+        })
+        .sum();
+    // aarch64 faults on a misaligned sp
+    let size = (size as i64 + 15) & !15;
+    program.push(Instr::Sub {
+        dst: sp,
+        src: Operand::Imm(size),
+    });
+    program.push(Instr::Mov {
+        dst: cur,
+        src: Operand::Reg(sp),
+    });
+    for piece in pieces {
+        match piece {
+            Piece::Text(bytes) => {
+                needs.copy = true;
+                let label = pool.intern(program, bytes);
+                program.push(Instr::Mov {
+                    dst: buf,
+                    src: Operand::Label(label),
+                });
+                program.push(Instr::Mov {
+                    dst: arch.reg(RegRole::Arg3)?,
+                    src: Operand::Imm(bytes.len() as i64),
+                });
+                program.push(Instr::Call(runtime::COPY.to_string()));
+            }
+            Piece::Int(name, value) => {
+                needs.fmt_int = true;
+                fmt_int(program, arch, name, value)?;
+            }
+            Piece::Float(name, value, precision) => {
+                needs.fmt_float = true;
+                fmt_float(program, arch, name, value, *precision)?;
+            }
+        }
+    }
+    // write(1, sp, cur - sp)
+    program.push(Instr::Mov {
+        dst: buf,
+        src: Operand::Reg(sp),
+    });
+    program.push(Instr::Sub {
+        dst: cur,
+        src: Operand::Reg(buf),
+    });
+    program.push(Instr::Mov {
+        dst: arch.reg(RegRole::Arg0)?,
+        src: Operand::Imm(1),
+    });
+    program.push(Instr::Mov {
+        dst: arch.reg(RegRole::SyscallNr)?,
+        src: Operand::Imm(abi.write as i64),
+    });
+    program.push(Instr::Syscall(abi.trap));
+    program.push(Instr::Add {
+        dst: sp,
+        src: Operand::Imm(size),
+    });
+    Ok(())
+}
+
+// the ir condition for a comparison
+fn cond(compare: Compare, unsigned: bool) -> Cond {
+    match (compare, unsigned) {
+        (Compare::Eq, _) => Cond::Eq,
+        (Compare::Ne, _) => Cond::Ne,
+        (Compare::Lt, false) => Cond::Lt,
+        (Compare::Le, false) => Cond::Le,
+        (Compare::Gt, false) => Cond::Gt,
+        (Compare::Ge, false) => Cond::Ge,
+        (Compare::Lt, true) => Cond::Below,
+        (Compare::Le, true) => Cond::BelowEq,
+        (Compare::Gt, true) => Cond::Above,
+        (Compare::Ge, true) => Cond::AboveEq,
+    }
+}
+
+// the comparison that holds exactly when `compare` doesn't
+fn inverse(compare: Compare) -> Compare {
+    match compare {
+        Compare::Eq => Compare::Ne,
+        Compare::Ne => Compare::Eq,
+        Compare::Lt => Compare::Ge,
+        Compare::Le => Compare::Gt,
+        Compare::Gt => Compare::Le,
+        Compare::Ge => Compare::Lt,
+    }
+}
+
+// what lowering carries from one statement to the next
+struct Lowering<'a> {
+    core_asm: &'a CoreAsm,
+    arch: &'a Target,
+    abi: SyscallAbi,
+    program: Program,
+    pool: StrPool,
+    needs: runtime::Needs,
+    // numbers the labels of each branch, so nested ones don't collide
+    labels: usize,
+    // where `continue` and `break` jump to, innermost loop last
+    loops: Vec<(String, String)>,
+}
+
+impl<'a> Lowering<'a> {
+    fn label(&mut self, kind: &str) -> String {
+        self.labels += 1;
+        format!("__coreasm_{}_{}", kind, self.labels)
+    }
+
+    // every print is one write: text known at compile time goes out straight
+    // from the data section, anything else is formatted on the stack first
+    fn print(&mut self, print: &'a Print) -> Result<(), MakeError> {
+        let pieces = pieces(self.core_asm, print)?;
+        match pieces.as_slice() {
+            [] => {}
+            // lengths are in utf-8 bytes, the same bytes the data item holds
+            [Piece::Text(bytes)] => {
+                let label = self.pool.intern(&mut self.program, bytes);
+                write(&mut self.program, &self.abi, self.arch, &label, bytes.len())?;
+            }
+            _ => buffered(
+                &mut self.program,
+                &mut self.pool,
+                &mut self.needs,
+                &self.abi,
+                self.arch,
+                &pieces,
+            )?,
+        }
+        Ok(())
+    }
+
+    fn statements(&mut self, statements: &'a [Statement]) -> Result<(), MakeError> {
+        for statement in statements {
+            match statement {
+                Statement::Print(print) => self.print(print)?,
+                Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                } => {
+                    let end = self.label("endif");
+                    if otherwise.is_empty() {
+                        self.branch_unless(condition, &end)?;
+                        self.statements(then)?;
+                    } else {
+                        let other = self.label("else");
+                        self.branch_unless(condition, &other)?;
+                        self.statements(then)?;
+                        self.program.push(Instr::Jump(end.clone()));
+                        self.program.push(Instr::Label(other));
+                        self.statements(otherwise)?;
                     }
+                    self.program.push(Instr::Label(end));
+                }
+                Statement::While { condition, body } => {
+                    let top = self.label("while");
+                    let end = self.label("endwhile");
+                    self.program.push(Instr::Label(top.clone()));
+                    self.branch_unless(condition, &end)?;
+                    self.body(body, &top, &end)?;
+                    self.program.push(Instr::Jump(top));
+                    self.program.push(Instr::Label(end));
                 }
-                PrintToken::Newline => {
-                    entry_section.push_str(&format!(
-                        "{} {}, {}\n",
-                        arch_map.get("mov").unwrap(),
-                        arch_map.get("Dest Index Register (i64)").unwrap(),
-                        arch_map.get("Syscall Write").unwrap()
-                    ));
-                    entry_section.push_str(&format!(
-                        "{} {}, jnl\n",
-                        arch_map.get("mov").unwrap(),
-                        arch_map.get("rsi").unwrap()
-                    ));
-                    entry_section.push_str(&format!(
-                        "{} {}, 1\n",
-                        arch_map.get("mov").unwrap(),
-                        arch_map.get("rdx").unwrap()
-                    ));
-                    entry_section.push_str(&format!(
-                        "{} {}, {}\n",
-                        arch_map.get("mov").unwrap(),
-                        arch_map.get("rax").unwrap(),
-                        arch_map.get("Syscall Write").unwrap()
-                    ));
-                    entry_section.push_str(&format!("{}\n", arch_map.get("Syscall").unwrap()));
+                Statement::For {
+                    var,
+                    from,
+                    to,
+                    step,
+                    body,
+                } => self.for_loop(var, from, to, *step, body)?,
+                Statement::Set { var, value } => {
+                    let wide = self.is_i64(var)?;
+                    let [lo, hi] = [self.arch.reg(RegRole::Arg0)?, self.arch.reg(RegRole::Arg1)?];
+                    self.load(value, wide, lo, hi)?;
+                    self.store(var, wide, lo, hi)?;
+                }
+                Statement::Add { var, value } => self.add(var, value, false)?,
+                Statement::Sub { var, value } => self.add(var, value, true)?,
+                Statement::Break | Statement::Continue => {
+                    let leaves = matches!(statement, Statement::Break);
+                    let Some((next, end)) = self.loops.last() else {
+                        let name = if leaves { "break" } else { "continue" };
+                        return Err(MakeError::OutsideLoop(name.to_string()));
+                    };
+                    let target = if leaves { end } else { next };
+                    self.program.push(Instr::Jump(target.clone()));
                 }
             }
         }
+        Ok(())
     }
 
-    asm_code.push_str("SECTION .data\n");
-    for line in data_section.lines() {
-        asm_code.push_str(&format!("     {}\n", line));
+    // a loop's body, with `continue` jumping to `next` and `break` to `end`
+    fn body(&mut self, body: &'a [Statement], next: &str, end: &str) -> Result<(), MakeError> {
+        self.loops.push((next.to_string(), end.to_string()));
+        let lowered = self.statements(body);
+        self.loops.pop();
+        lowered
     }
-    asm_code.push_str("SECTION .text\n      global _start\n_start:\n");
-    for line in entry_section.lines() {
-        asm_code.push_str(&format!("     {}\n", line));
+
+    // var = from, then while var < to (or > to, counting down) the body and
+    // var += step
+    fn for_loop(
+        &mut self,
+        var: &str,
+        from: &Value,
+        to: &Value,
+        step: i64,
+        body: &'a [Statement],
+    ) -> Result<(), MakeError> {
+        let wide = self.is_i64(var)?;
+        if step == 0 || !wide && i32::try_from(step).is_err() {
+            return Err(MakeError::BadStep {
+                var: var.to_string(),
+                step,
+            });
+        }
+        let [lo, hi] = [self.arch.reg(RegRole::Arg0)?, self.arch.reg(RegRole::Arg1)?];
+        self.load(from, wide, lo, hi)?;
+        self.store(var, wide, lo, hi)?;
+
+        let top = self.label("for");
+        let next = self.label("next");
+        let end = self.label("endfor");
+        self.program.push(Instr::Label(top.clone()));
+        let compare = if step > 0 { Compare::Lt } else { Compare::Gt };
+        self.branch_unless(&Condition::new(var, compare, to.clone()), &end)?;
+        self.body(body, &next, &end)?;
+        self.program.push(Instr::Label(next));
+        self.add(var, &Value::Const(step), false)?;
+        self.program.push(Instr::Jump(top));
+        self.program.push(Instr::Label(end));
+        Ok(())
+    }
+
+    // whether an integer variable is I64 rather than I32
+    fn is_i64(&self, var: &str) -> Result<bool, MakeError> {
+        match self.var_type(var)? {
+            Types::I64 => Ok(true),
+            Types::I32 => Ok(false),
+            found => Err(MakeError::TypeMismatch {
+                var: var.to_string(),
+                expected: Types::I64,
+                found,
+            }),
+        }
     }
-    //println!("exit : {}", core_asm.exit);
+
+    // adds `value` to an integer variable in memory, or takes it away
+    fn add(&mut self, var: &str, value: &Value, subtract: bool) -> Result<(), MakeError> {
+        let wide = self.is_i64(var)?;
+        let [lo, hi, value_lo, value_hi] = [
+            self.arch.reg(RegRole::Arg0)?,
+            self.arch.reg(RegRole::Arg1)?,
+            self.arch.reg(RegRole::Arg2)?,
+            self.arch.reg(RegRole::Arg3)?,
+        ];
+        self.load(&Value::Var(var.to_string()), wide, lo, hi)?;
+        self.load(value, wide, value_lo, value_hi)?;
+        let op = |dst, src| match subtract {
+            true => Instr::Sub { dst, src },
+            false => Instr::Add { dst, src },
+        };
+        if !(wide && self.arch.bit == Bit::X32) {
+            self.program.push(op(lo, Operand::Reg(value_lo)));
+            return self.store(var, wide, lo, hi);
+        }
+
+        // the low words first. a sum carried if it came out below what was
+        // added, and a difference borrowed if more was taken than there was
+        let settled = self.label(if subtract { "noborrow" } else { "nocarry" });
+        if !subtract {
+            self.program.push(op(lo, Operand::Reg(value_lo)));
+        }
+        self.program.push(Instr::Cmp {
+            lhs: lo,
+            rhs: Operand::Reg(value_lo),
+        });
+        self.program.push(Instr::JumpIf {
+            cond: Cond::AboveEq,
+            target: settled.clone(),
+        });
+        self.program.push(op(hi, Operand::Imm(1)));
+        self.program.push(Instr::Label(settled));
+        if subtract {
+            self.program.push(op(lo, Operand::Reg(value_lo)));
+        }
+        self.program.push(op(hi, Operand::Reg(value_hi)));
+        self.store(var, wide, lo, hi)
+    }
+
+    // writes `lo` back to an integer variable, with `hi` as the high word
+    // when a 64-bit value is split across a 32-bit target's words
+    fn store(
+        &mut self,
+        var: &str,
+        wide: bool,
+        lo: Register,
+        hi: Register,
+    ) -> Result<(), MakeError> {
+        let address = self.arch.reg(RegRole::Arg2)?;
+        self.program.push(Instr::Mov {
+            dst: address,
+            src: Operand::Label(symbol(var)),
+        });
+        let stores = match (wide, self.arch.bit) {
+            (true, Bit::X32) => vec![(lo, 0, Width::W32), (hi, 4, Width::W32)],
+            (true, _) => vec![(lo, 0, Width::W64)],
+            (false, _) => vec![(lo, 0, Width::W32)],
+        };
+        for (src, offset, width) in stores {
+            self.program.push(Instr::Store {
+                dst: Mem {
+                    base: address,
+                    offset,
+                },
+                src,
+                width,
+            });
+        }
+        Ok(())
+    }
+
+    // whether a condition compares 64-bit values, checking that both sides
+    // are integers
+    fn is_wide(&self, condition: &Condition) -> Result<bool, MakeError> {
+        let mut wide = false;
+        for value in [&condition.left, &condition.right] {
+            wide |= match value {
+                Value::Var(name) => match self.var_type(name)? {
+                    Types::I64 => true,
+                    Types::I32 => false,
+                    found => {
+                        return Err(MakeError::TypeMismatch {
+                            var: name.clone(),
+                            expected: Types::I64,
+                            found,
+                        })
+                    }
+                },
+                // an unsigned 32-bit comparison takes 0..=u32::MAX as well
+                Value::Const(n) => {
+                    i32::try_from(*n).is_err() && !(condition.unsigned && u32::try_from(*n).is_ok())
+                }
+            };
+        }
+        Ok(wide)
+    }
+
+    fn var_type(&self, name: &str) -> Result<Types, MakeError> {
+        self.core_asm
+            .data
+            .get(name)
+            .map(|var| var.var_type)
+            .ok_or_else(|| MakeError::UndefinedVariable(name.to_string()))
+    }
+
+    // puts one side of a comparison in `lo`, or in `lo` and `hi` when a
+    // 64-bit comparison is split across a 32-bit target's words. narrow
+    // values end up sign extended to the register, like a W32 load
+    fn load(
+        &mut self,
+        value: &Value,
+        wide: bool,
+        lo: Register,
+        hi: Register,
+    ) -> Result<(), MakeError> {
+        let pair = wide && self.arch.bit == Bit::X32;
+        let name = match value {
+            Value::Const(n) if pair => {
+                self.program.push(Instr::Mov {
+                    dst: hi,
+                    src: Operand::Imm((n >> 32) as i32 as i64),
+                });
+                self.program.push(Instr::Mov {
+                    dst: lo,
+                    src: Operand::Imm(*n as i32 as i64),
+                });
+                return Ok(());
+            }
+            Value::Const(n) => {
+                let n = if wide { *n } else { *n as i32 as i64 };
+                self.program.push(Instr::Mov {
+                    dst: lo,
+                    src: Operand::Imm(n),
+                });
+                return Ok(());
+            }
+            Value::Var(name) => name,
+        };
+        let wide_var = self.var_type(name)? == Types::I64;
+        let base = if pair { hi } else { lo };
+        self.program.push(Instr::Mov {
+            dst: base,
+            src: Operand::Label(symbol(name)),
+        });
+        let width = if wide_var && !pair {
+            Width::W64
+        } else {
+            Width::W32
+        };
+        self.program.push(Instr::Load {
+            dst: lo,
+            src: Mem { base, offset: 0 },
+            width,
+        });
+        match (pair, wide_var) {
+            (true, true) => self.program.push(Instr::Load {
+                dst: hi,
+                src: Mem { base, offset: 4 },
+                width,
+            }),
+            // the high word of a sign extended I32: 0, or -1 if it's negative
+            (true, false) => {
+                let positive = self.label("positive");
+                self.program.push(Instr::Mov {
+                    dst: hi,
+                    src: Operand::Imm(0),
+                });
+                self.program.push(Instr::Cmp {
+                    lhs: lo,
+                    rhs: Operand::Imm(0),
+                });
+                self.program.push(Instr::JumpIf {
+                    cond: Cond::Ge,
+                    target: positive.clone(),
+                });
+                self.program.push(Instr::Mov {
+                    dst: hi,
+                    src: Operand::Imm(-1),
+                });
+                self.program.push(Instr::Label(positive));
+            }
+            (false, _) => {}
+        }
+        Ok(())
+    }
+
+    // compares and jumps to `target` unless the condition holds, falling
+    // through if it does
+    fn branch_unless(&mut self, condition: &Condition, target: &str) -> Result<(), MakeError> {
+        let wide = self.is_wide(condition)?;
+        let [left, left_hi, right, right_hi] = [
+            self.arch.reg(RegRole::Arg0)?,
+            self.arch.reg(RegRole::Arg1)?,
+            self.arch.reg(RegRole::Arg2)?,
+            self.arch.reg(RegRole::Arg3)?,
+        ];
+        self.load(&condition.left, wide, left, left_hi)?;
+        self.load(&condition.right, wide, right, right_hi)?;
+        let compare = condition.compare;
+        let fails = |unsigned| Instr::JumpIf {
+            cond: cond(inverse(compare), unsigned),
+            target: target.to_string(),
+        };
+        if !(wide && self.arch.bit == Bit::X32) {
+            self.program.push(Instr::Cmp {
+                lhs: left,
+                rhs: Operand::Reg(right),
+            });
+            self.program.push(fails(condition.unsigned));
+            return Ok(());
+        }
+
+        // the high words decide unless they're equal, then the low words
+        // decide as unsigned numbers
+        let holds = self.label("holds");
+        self.program.push(Instr::Cmp {
+            lhs: left_hi,
+            rhs: Operand::Reg(right_hi),
+        });
+        let jump = |cond, target: &str| Instr::JumpIf {
+            cond,
+            target: target.to_string(),
+        };
+        let (above, below) = (
+            cond(Compare::Gt, condition.unsigned),
+            cond(Compare::Lt, condition.unsigned),
+        );
+        match compare {
+            Compare::Eq => self.program.push(jump(Cond::Ne, target)),
+            Compare::Ne => self.program.push(jump(Cond::Ne, &holds)),
+            Compare::Lt | Compare::Le => {
+                self.program.push(jump(below, &holds));
+                self.program.push(jump(above, target));
+            }
+            Compare::Gt | Compare::Ge => {
+                self.program.push(jump(above, &holds));
+                self.program.push(jump(below, target));
+            }
+        }
+        self.program.push(Instr::Cmp {
+            lhs: left,
+            rhs: Operand::Reg(right),
+        });
+        self.program.push(fails(true));
+        self.program.push(Instr::Label(holds));
+        Ok(())
+    }
+}
+
+// lowers a CoreAsm program to target instructions, ready for an emitter.
+// the program is validated first, since hand-built ones never went through
+// the builder or a loader
+pub fn lower(core_asm: &CoreAsm, arch: &Target) -> Result<Program, MakeError> {
+    core_asm.validate()?;
+    let mut lowering = Lowering {
+        core_asm,
+        arch,
+        abi: arch.syscall_abi()?,
+        program: Program::new("_start"),
+        pool: StrPool::default(),
+        needs: runtime::Needs::default(),
+        labels: 0,
+        loops: Vec::new(),
+    };
+
+    for (name, var) in &core_asm.data.variables {
+        let value = match &var.value {
+            VarValue::I32(i) => DataValue::I32(*i),
+            VarValue::I64(i) => DataValue::I64(*i),
+            VarValue::F32(f) => DataValue::F32(*f),
+            VarValue::F64(f) => DataValue::F64(*f),
+            VarValue::String(s) => {
+                lowering.pool.seed(s.as_bytes(), &symbol(name));
+                DataValue::Bytes(s.as_bytes().to_vec())
+            }
+        };
+        lowering.program.data(&symbol(name), value);
+    }
+
+    for print in &core_asm.prints {
+        lowering.print(print)?;
+    }
+    lowering.statements(&core_asm.statements)?;
+
+    let Lowering {
+        mut program,
+        abi,
+        needs,
+        ..
+    } = lowering;
     if core_asm.exit {
-        // Dynamically retrieve registers and values from the architecture map
-        let rax_reg = arch_map.get("rax").unwrap();
-        let rdi_reg = arch_map.get("rdi").unwrap();
-        let exit_syscall = arch_map.get("exit code").unwrap(); // 60 is the syscall number for exit on x86_64
-        let exit_code = "0";
-
-        asm_code.push_str(
-            format!(
-                "     {} {}, {}\n",
-                arch_map.get("mov").unwrap(),
-                rax_reg,
-                exit_syscall
-            )
-            .as_str(),
+        let code = Operand::Imm(core_asm.exit_code.into());
+        syscall(&mut program, &abi, arch, abi.exit, vec![code])?;
+    }
+
+    runtime::append(&mut program, arch, &needs)?;
+
+    Ok(program)
+}
+
+pub fn maker(core_asm: &CoreAsm, arch: Target) -> Result<String, MakeError> {
+    let syntax = Syntax::default_for(&arch);
+    maker_with_syntax(core_asm, arch, syntax)
+}
+
+pub fn maker_with_syntax(
+    core_asm: &CoreAsm,
+    arch: Target,
+    syntax: Syntax,
+) -> Result<String, MakeError> {
+    if arch.arch == Arch::Arm && syntax == Syntax::Nasm {
+        return Err(MakeError::UnsupportedSyntax {
+            target: arch.to_string(),
+            syntax,
+        });
+    }
+    let program = lower(core_asm, &arch)?;
+    Ok(match syntax {
+        Syntax::Nasm => emit::nasm(&program, &arch),
+        _ => emit::gas(&program, &arch, syntax),
+    })
+}
+
+// a static executable, built without an assembler or linker
+pub fn executable(core_asm: &CoreAsm, arch: Target) -> Result<Vec<u8>, MakeError> {
+    let program = lower(core_asm, &arch)?;
+    let object = encode::encode(&program, &arch)?;
+    elf::executable(&object, &arch)
+}
+
+// a relocatable object for the system linker, built without an assembler
+pub fn object(core_asm: &CoreAsm, arch: Target) -> Result<Vec<u8>, MakeError> {
+    let program = lower(core_asm, &arch)?;
+    let object = encode::encode(&program, &arch)?;
+    elf::relocatable(&object, &arch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{Bit, Trap, OS};
+    use crate::coreasm::{Print, Types, Var};
+
+    fn program() -> CoreAsm {
+        let mut asm = CoreAsm::new();
+        for (i, name) in ["zeta", "alpha", "mid", "beta", "omega", "gamma"]
+            .iter()
+            .enumerate()
+        {
+            asm.data.mkvar(
+                name.to_string(),
+                Types::String,
+                VarValue::String(format!("value {}", i)),
+            );
+        }
+        let mut print = Print::new();
+        print.add_token(PrintToken::Variable("mid".to_string()));
+        print.add_token(PrintToken::Text("!".to_string()));
+        print.add_token(PrintToken::Newline);
+        asm.prints.push(print);
+        asm.exit(true);
+        asm
+    }
+
+    fn target() -> Target {
+        Target::new(Bit::X64, Arch::X86, OS::Linux)
+    }
+
+    #[test]
+    fn output_is_byte_identical_across_runs() {
+        let first = maker(&program(), target()).unwrap();
+        for _ in 0..20 {
+            assert_eq!(maker(&program(), target()).unwrap(), first);
+        }
+    }
+
+    // the length register of every write, in order
+    fn write_lengths(asm: &CoreAsm) -> Vec<i64> {
+        let len = target().reg(RegRole::Arg2).unwrap();
+        lower(asm, &target())
+            .unwrap()
+            .text
+            .into_iter()
+            .filter_map(|i| match i {
+                Instr::Mov {
+                    dst,
+                    src: Operand::Imm(n),
+                } if dst == len => Some(n),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lengths_count_encoded_bytes() {
+        let mut asm = CoreAsm::new();
+        asm.data.mkvar(
+            "greeting".to_string(),
+            Types::String,
+            VarValue::String("grüße 👋".to_string()),
+        );
+        let mut print = Print::new();
+        print.add_token(PrintToken::Text("tab\there \"quoted\" \\ ✓\n".to_string()));
+        asm.prints.push(print);
+        let mut print = Print::new();
+        print.add_token(PrintToken::Variable("greeting".to_string()));
+        asm.prints.push(print);
+        // 21 ascii bytes + 3 for the check mark; ü and ß take 2, the emoji 4
+        assert_eq!(write_lengths(&asm), [24, 12]);
+
+        let program = lower(&asm, &target()).unwrap();
+        let text = program
+            .data
+            .iter()
+            .find(|d| d.label == "__coreasm_str_0")
+            .unwrap();
+        assert_eq!(
+            text.value,
+            DataValue::Bytes("tab\there \"quoted\" \\ ✓\n".as_bytes().to_vec())
         );
+    }
 
-        asm_code.push_str(
-            format!(
-                "     {} {}, {}\n",
-                arch_map.get("mov").unwrap(),
-                rdi_reg,
-                exit_code
-            )
-            .as_str(),
+    #[test]
+    fn data_section_follows_declaration_order() {
+        let mut asm = program();
+        // redefining keeps the original slot
+        asm.data.mkvar(
+            "alpha".to_string(),
+            Types::String,
+            VarValue::String("again".to_string()),
+        );
+        let labels: Vec<String> = lower(&asm, &target())
+            .unwrap()
+            .data
+            .into_iter()
+            .map(|item| item.label)
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                "cv_zeta",
+                "cv_alpha",
+                "cv_mid",
+                "cv_beta",
+                "cv_omega",
+                "cv_gamma",
+                "__coreasm_str_0"
+            ]
         );
+    }
 
-        // Trigger the syscall
-        asm_code.push_str(format!("     {}\n", arch_map.get("Syscall").unwrap()).as_str());
+    #[test]
+    fn variables_dont_read_as_registers_or_keywords() {
+        let mut asm = CoreAsm::new();
+        for name in ["offset", "rax", "sp"] {
+            asm.data
+                .mkvar(name.to_string(), Types::I64, VarValue::I64(7));
+            let mut print = Print::new();
+            print.add_token(PrintToken::Variable(name.to_string()));
+            asm.prints.push(print);
+        }
+        for syntax in [Syntax::Nasm, Syntax::GasIntel, Syntax::GasAtt] {
+            let text = maker_with_syntax(&asm, target(), syntax).unwrap();
+            // the definition and the load that prints it
+            for name in ["offset", "rax", "sp"] {
+                let label = format!("cv_{name}");
+                assert_eq!(text.matches(&label).count(), 2, "{syntax:?}");
+            }
+        }
     }
 
-    asm_code
+    #[test]
+    fn arm_has_one_gnu_syntax() {
+        let arm = Target::new(Bit::X64, Arch::Arm, OS::Linux);
+        assert_eq!(Syntax::default_for(&arm), Syntax::GasAtt);
+        let att = maker_with_syntax(&program(), arm, Syntax::GasAtt).unwrap();
+        assert_eq!(
+            maker_with_syntax(&program(), arm, Syntax::GasIntel).unwrap(),
+            att
+        );
+        assert!(!att.contains(".intel_syntax"));
+    }
+
+    #[test]
+    fn text_is_pooled_and_merged() {
+        let mut asm = CoreAsm::new();
+        asm.data.mkvar(
+            "n".to_string(),
+            Types::String,
+            VarValue::String("joy".to_string()),
+        );
+        for _ in 0..2 {
+            let mut print = Print::new();
+            print.add_token(PrintToken::Text("Hello ".to_string()));
+            print.add_token(PrintToken::Variable("n".to_string()));
+            print.add_token(PrintToken::Text("!".to_string()));
+            print.add_token(PrintToken::Newline);
+            asm.prints.push(print);
+        }
+        for token in [PrintToken::Newline, PrintToken::Variable("n".to_string())] {
+            let mut print = Print::new();
+            print.add_token(token);
+            asm.prints.push(print);
+        }
+
+        let program = lower(&asm, &target()).unwrap();
+        let data: Vec<(String, DataValue)> = program
+            .data
+            .into_iter()
+            .map(|d| (d.label, d.value))
+            .collect();
+        let bytes = |s: &str| DataValue::Bytes(s.as_bytes().to_vec());
+        assert_eq!(
+            data,
+            [
+                ("cv_n".to_string(), bytes("joy")),
+                ("__coreasm_str_0".to_string(), bytes("Hello joy!\n")),
+                ("__coreasm_str_1".to_string(), bytes("\n")),
+            ]
+        );
+        // one write per print; a lone string variable is written from its own label
+        assert_eq!(write_lengths(&asm), [11, 11, 1, 3]);
+    }
+
+    #[test]
+    fn runtime_values_share_one_write() {
+        let mut asm = CoreAsm::new();
+        asm.data
+            .mkvar("x".to_string(), Types::I64, VarValue::I64(-42));
+        asm.data
+            .mkvar("f".to_string(), Types::F32, VarValue::F32(1.5));
+        let mut print = Print::new();
+        print.add_token(PrintToken::Text("x = ".to_string()));
+        print.add_token(PrintToken::Variable("x".to_string()));
+        print.add_token(PrintToken::Text(", f = ".to_string()));
+        print.add_token(PrintToken::Float {
+            var: "f".to_string(),
+            precision: 2,
+        });
+        print.add_token(PrintToken::Newline);
+        asm.prints.push(print);
+
+        let text = lower(&asm, &target()).unwrap().text;
+        let count = |want: &Instr| text.iter().filter(|i| *i == want).count();
+        assert_eq!(count(&Instr::Syscall(Trap::Syscall)), 1);
+        assert_eq!(count(&Instr::Call(runtime::COPY.to_string())), 3);
+        assert_eq!(count(&Instr::Call(runtime::FMT_F64.to_string())), 1);
+    }
+
+    #[test]
+    fn ifs_branch_past_what_doesnt_run() {
+        let mut asm = CoreAsm::new();
+        asm.data
+            .mkvar("n".to_string(), Types::I32, VarValue::I32(3));
+        let said = |text: &str| {
+            let mut print = Print::new();
+            print.add_token(PrintToken::Text(text.to_string()));
+            Statement::Print(print)
+        };
+        let inner = Statement::If {
+            condition: Condition::new("n", Compare::Lt, 10).unsigned(),
+            then: vec![said("small")],
+            otherwise: Vec::new(),
+        };
+        asm.statements.push(Statement::If {
+            condition: Condition::new("n", Compare::Gt, 0),
+            then: vec![inner],
+            otherwise: vec![said("not positive")],
+        });
+
+        let text = lower(&asm, &target()).unwrap().text;
+        let jumps: Vec<(Cond, &str)> = text
+            .iter()
+            .filter_map(|i| match i {
+                Instr::JumpIf { cond, target } => Some((*cond, target.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            jumps,
+            [
+                (Cond::Le, "__coreasm_else_2"),
+                (Cond::AboveEq, "__coreasm_endif_3")
+            ]
+        );
+        let labels: Vec<&str> = text
+            .iter()
+            .filter_map(|i| match i {
+                Instr::Label(label) if label.starts_with("__coreasm_e") => Some(label.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            ["__coreasm_endif_3", "__coreasm_else_2", "__coreasm_endif_1"]
+        );
+        assert!(text.contains(&Instr::Jump("__coreasm_endif_1".to_string())));
+    }
+
+    #[test]
+    fn loops_jump_back_and_out() {
+        let mut asm = CoreAsm::new();
+        asm.data
+            .mkvar("i".to_string(), Types::I64, VarValue::I64(0));
+        asm.statements.push(Statement::For {
+            var: "i".to_string(),
+            from: Value::Const(0),
+            to: Value::Const(10),
+            step: 3,
+            body: vec![Statement::While {
+                condition: Condition::new("i", Compare::Ne, 5),
+                body: vec![Statement::Continue, Statement::Break],
+            }],
+        });
+
+        let text = lower(&asm, &target()).unwrap().text;
+        let jumps: Vec<&str> = text
+            .iter()
+            .filter_map(|i| match i {
+                Instr::Jump(target) | Instr::JumpIf { target, .. } => Some(target.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            jumps,
+            [
+                "__coreasm_endfor_3",
+                "__coreasm_endwhile_5",
+                "__coreasm_while_4",
+                "__coreasm_endwhile_5",
+                "__coreasm_while_4",
+                "__coreasm_for_1",
+            ]
+        );
+        // the variable is stepped in memory, a word at a time on 32-bit
+        // targets, where the low words can carry
+        let i386 = Target::new(Bit::X32, Arch::X86, OS::Linux);
+        let text = lower(&asm, &i386).unwrap().text;
+        let stores = text
+            .iter()
+            .filter(|i| matches!(i, Instr::Store { .. }))
+            .count();
+        assert_eq!(stores, 4);
+        assert!(text
+            .iter()
+            .any(|i| matches!(i, Instr::Label(l) if l.starts_with("__coreasm_nocarry_"))));
+
+        asm.statements.push(Statement::Break);
+        assert!(matches!(
+            lower(&asm, &target()),
+            Err(MakeError::OutsideLoop(name)) if name == "break"
+        ));
+    }
+
+    #[test]
+    fn sums_carry_between_words() {
+        use crate::arch::X86Reg;
+        let mut asm = CoreAsm::new();
+        asm.data
+            .mkvar("w".to_string(), Types::I64, VarValue::I64(0));
+        asm.data
+            .mkvar("n".to_string(), Types::I32, VarValue::I32(-1));
+        asm.statements = vec![
+            Statement::Set {
+                var: "w".to_string(),
+                value: Value::from("n"),
+            },
+            Statement::Sub {
+                var: "w".to_string(),
+                value: Value::Const(5),
+            },
+            Statement::Add {
+                var: "n".to_string(),
+                value: Value::from("w"),
+            },
+        ];
+        // an i64 can't be added to an i32
+        assert!(matches!(
+            lower(&asm, &target()),
+            Err(MakeError::TypeMismatch { var, .. }) if var == "w"
+        ));
+        asm.statements.pop();
+        for triple in ["x86_64-linux", "i686-linux", "aarch64-linux", "armv7-linux"] {
+            assert!(maker(&asm, triple.parse().unwrap()).is_ok(), "{}", triple);
+        }
+
+        // w's words are in ebx (low) and ecx (high), 5's low word in edx; the
+        // high word gives up one when the low word borrows
+        let i386 = Target::new(Bit::X32, Arch::X86, OS::Linux);
+        let text = lower(&asm, &i386).unwrap().text;
+        let [lo, hi, five] = [X86Reg::Rbx, X86Reg::Rcx, X86Reg::Rdx].map(Register::X86);
+        let compare = Instr::Cmp {
+            lhs: lo,
+            rhs: Operand::Reg(five),
+        };
+        let borrow = text.iter().position(|i| *i == compare).unwrap();
+        let Instr::JumpIf {
+            cond: Cond::AboveEq,
+            target: settled,
+        } = &text[borrow + 1]
+        else {
+            panic!("expected a jump past the borrow");
+        };
+        assert_eq!(
+            text[borrow + 2..borrow + 5],
+            [
+                Instr::Sub {
+                    dst: hi,
+                    src: Operand::Imm(1),
+                },
+                Instr::Label(settled.clone()),
+                Instr::Sub {
+                    dst: lo,
+                    src: Operand::Reg(five),
+                },
+            ]
+        );
+        assert!(settled.starts_with("__coreasm_noborrow_"));
+    }
+
+    #[test]
+    fn mistakes_stop_lowering() {
+        for target in [
+            Target::new(Bit::X64, Arch::X86, OS::Windows),
+            Target::new(Bit::X64, Arch::Arm, OS::Mac),
+        ] {
+            assert!(matches!(
+                maker(&program(), target),
+                Err(MakeError::UnsupportedTarget(t)) if t == target.to_string()
+            ));
+        }
+
+        let mut asm = program();
+        let mut print = Print::new();
+        print.add_token(PrintToken::Variable("nope".to_string()));
+        asm.prints.push(print);
+        assert!(matches!(
+            maker(&asm, target()),
+            Err(MakeError::UndefinedVariable(name)) if name == "nope"
+        ));
+
+        let mut asm = CoreAsm::new();
+        asm.data
+            .mkvar("n".to_string(), Types::I32, VarValue::I64(1));
+        assert!(matches!(
+            maker(&asm, target()),
+            Err(MakeError::TypeMismatch {
+                var,
+                expected: Types::I32,
+                found: Types::I64,
+            }) if var == "n"
+        ));
+
+        // hand-built programs are validated too
+        let mut asm = CoreAsm::new();
+        asm.data
+            .mkvar("_start".to_string(), Types::I32, VarValue::I32(0));
+        assert!(matches!(
+            lower(&asm, &target()),
+            Err(MakeError::BadName(name)) if name == "_start"
+        ));
+        let mut asm = program();
+        asm.data.variables.push((
+            "zeta".to_string(),
+            Var {
+                var_type: Types::I32,
+                value: VarValue::I32(0),
+            },
+        ));
+        assert!(matches!(
+            lower(&asm, &target()),
+            Err(MakeError::DuplicateVariable(name)) if name == "zeta"
+        ));
+
+        // nothing says whether the print comes before or after the if
+        let mut asm = program();
+        asm.statements.push(Statement::If {
+            condition: Condition::new(1, Compare::Eq, 1),
+            then: Vec::new(),
+            otherwise: Vec::new(),
+        });
+        assert!(matches!(
+            lower(&asm, &target()),
+            Err(MakeError::MixedPrints)
+        ));
+
+        // an i32 counter could never reach an end past i32::MAX
+        let mut asm = CoreAsm::new();
+        asm.data
+            .mkvar("i".to_string(), Types::I32, VarValue::I32(0));
+        asm.data
+            .mkvar("far".to_string(), Types::I64, VarValue::I64(1));
+        for (to, blamed) in [
+            (Value::Const(3_000_000_000), "i"),
+            (Value::from("far"), "far"),
+        ] {
+            asm.statements = vec![Statement::For {
+                var: "i".to_string(),
+                from: Value::Const(2_147_483_640),
+                to,
+                step: 4,
+                body: Vec::new(),
+            }];
+            assert!(matches!(
+                lower(&asm, &target()),
+                Err(MakeError::TypeMismatch {
+                    var,
+                    expected: Types::I32,
+                    found: Types::I64,
+                }) if var == blamed
+            ));
+        }
+    }
+
+    // the write and exit sequences, spelled out in each target's registers
+    // so a mix-up in the register maps can't cancel itself out
+    #[test]
+    fn syscalls_follow_each_linux_abi() {
+        use crate::arch::{A64Reg, ArmReg, X86Reg};
+        let mut asm = CoreAsm::new();
+        let mut print = Print::new();
+        print.add_token(PrintToken::Text("hi\n".to_string()));
+        asm.prints.push(print);
+        asm.exit_with(5);
+
+        let text = "__coreasm_str_0";
+        let cases = [
+            (
+                Target::new(Bit::X64, Arch::X86, OS::Linux),
+                [X86Reg::Rax, X86Reg::Rdi, X86Reg::Rsi, X86Reg::Rdx].map(Register::X86),
+                (1, 60),
+                Trap::Syscall,
+                "syscall",
+            ),
+            (
+                Target::new(Bit::X32, Arch::X86, OS::Linux),
+                [X86Reg::Rax, X86Reg::Rbx, X86Reg::Rcx, X86Reg::Rdx].map(Register::X86),
+                (4, 1),
+                Trap::Int80,
+                "int 0x80",
+            ),
+            (
+                Target::new(Bit::X64, Arch::Arm, OS::Linux),
+                [A64Reg::X8, A64Reg::X0, A64Reg::X1, A64Reg::X2].map(Register::A64),
+                (64, 93),
+                Trap::Svc,
+                "svc 0",
+            ),
+            (
+                Target::new(Bit::X32, Arch::Arm, OS::Linux),
+                [ArmReg::R7, ArmReg::R0, ArmReg::R1, ArmReg::R2].map(Register::Arm),
+                (4, 1),
+                Trap::Svc,
+                "svc 0",
+            ),
+        ];
+        for (target, [nr, fd, buf, len], (write, exit), trap, written) in cases {
+            let mov = |dst, src| Instr::Mov { dst, src };
+            let expected = [
+                mov(fd, Operand::Imm(1)),
+                mov(buf, Operand::Label(text.to_string())),
+                mov(len, Operand::Imm(3)),
+                mov(nr, Operand::Imm(write)),
+                Instr::Syscall(trap),
+                mov(fd, Operand::Imm(5)),
+                mov(nr, Operand::Imm(exit)),
+                Instr::Syscall(trap),
+            ];
+            let lowered = lower(&asm, &target).unwrap().text;
+            assert_eq!(lowered, expected, "{}", target);
+            let code = maker(&asm, target).unwrap();
+            assert_eq!(code.lines().filter(|l| l.trim() == written).count(), 2);
+        }
+    }
+
+    #[test]
+    fn lowering_builds_the_whole_program() {
+        let mut asm = CoreAsm::new();
+        asm.data.mkvar(
+            "name".to_string(),
+            Types::String,
+            VarValue::String("joy".to_string()),
+        );
+        let mut print = Print::new();
+        print.add_token(PrintToken::Variable("name".to_string()));
+        asm.prints.push(print);
+        asm.exit(true);
+
+        let reg = |role| target().reg(role).unwrap();
+        let mut expected = Program::new("_start");
+        expected.data("cv_name", DataValue::Bytes(b"joy".to_vec()));
+        for instr in [
+            Instr::Mov {
+                dst: reg(RegRole::Arg0),
+                src: Operand::Imm(1),
+            },
+            Instr::Mov {
+                dst: reg(RegRole::Arg1),
+                src: Operand::Label("cv_name".to_string()),
+            },
+            Instr::Mov {
+                dst: reg(RegRole::Arg2),
+                src: Operand::Imm(3),
+            },
+            Instr::Mov {
+                dst: reg(RegRole::SyscallNr),
+                src: Operand::Imm(1),
+            },
+            Instr::Syscall(Trap::Syscall),
+            Instr::Mov {
+                dst: reg(RegRole::Arg0),
+                src: Operand::Imm(0),
+            },
+            Instr::Mov {
+                dst: reg(RegRole::SyscallNr),
+                src: Operand::Imm(60),
+            },
+            Instr::Syscall(Trap::Syscall),
+        ] {
+            expected.push(instr);
+        }
+        assert_eq!(lower(&asm, &target()).unwrap(), expected);
+        // maker is nothing more than lower and an emitter
+        assert_eq!(
+            maker(&asm, target()).unwrap(),
+            emit::nasm(&expected, &target())
+        );
+    }
+
+    #[test]
+    fn exit_status_reaches_the_syscall() {
+        let mut asm = CoreAsm::new();
+        asm.exit_with(7);
+        let status = target().reg(RegRole::Arg0).unwrap();
+        let text = lower(&asm, &target()).unwrap().text;
+        assert!(text.contains(&Instr::Mov {
+            dst: status,
+            src: Operand::Imm(7),
+        }));
+    }
 }
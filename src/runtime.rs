@@ -0,0 +1,673 @@
+use crate::{
+    arch::{Bit, RegRole, Register, Target, Width},
+    error::MakeError,
+    ir::{Cond, DataValue, Instr, Mem, Operand, Program},
+};
+
+// helper routines maker appends after the program when something needs them.
+// they only touch the syscall registers (SyscallNr, Arg0..Arg5), the float
+// scratch registers and the stack
+
+// the fmt routines append text at the cursor in Arg2 and leave it advanced.
+// value in Arg0 (low half) and Arg1 (high half on 32-bit targets)
+pub const FMT_I64: &str = "__coreasm_fmt_i64";
+// value in Arg0, sign extended before falling into FMT_I64
+pub const FMT_I32: &str = "__coreasm_fmt_i32";
+// double in FloatScratch, 10^precision in Arg3
+pub const FMT_F64: &str = "__coreasm_fmt_f64";
+// copies Arg3 bytes from Arg1 to the cursor
+pub const COPY: &str = "__coreasm_copy";
+const POW10: &str = "__coreasm_pow10";
+const FCONST: &str = "__coreasm_fconst";
+
+// the largest precision a float can be printed with; the scaled fraction has
+// to fit a 32-bit register
+pub const MAX_PRECISION: u32 = 9;
+
+// the most text each formatter appends: sign and 19 digits, or sign, up to
+// 309 integer digits, the point and the fraction
+pub const INT_CHARS: usize = 20;
+pub const FLOAT_CHARS: usize = 1 + 309 + 1 + MAX_PRECISION as usize;
+
+// which routines a lowered program calls
+#[derive(Default)]
+pub struct Needs {
+    pub fmt_int: bool,
+    pub fmt_float: bool,
+    pub copy: bool,
+}
+
+pub fn append(program: &mut Program, arch: &Target, needs: &Needs) -> Result<(), MakeError> {
+    if needs.copy {
+        copy(program, arch)?;
+    }
+    if needs.fmt_float {
+        fmt_float(program, arch)?;
+    }
+    if needs.fmt_int || needs.fmt_float {
+        fmt_int(program, arch)?;
+    }
+    Ok(())
+}
+
+// registers of the integer formatter. 32-bit targets hold the value and the
+// current power of ten as lo/hi pairs
+struct IntRegs {
+    pair: bool,
+    lo: Register,
+    hi: Register,
+    cur: Register,
+    table: Register,
+    plo: Register,
+    phi: Register,
+    digit: Register,
+}
+
+impl IntRegs {
+    fn new(arch: &Target) -> Result<Self, MakeError> {
+        Ok(IntRegs {
+            pair: arch.bit == Bit::X32,
+            lo: arch.reg(RegRole::Arg0)?,
+            hi: arch.reg(RegRole::Arg1)?,
+            cur: arch.reg(RegRole::Arg2)?,
+            table: arch.reg(RegRole::Arg3)?,
+            plo: arch.reg(RegRole::Arg4)?,
+            phi: arch.reg(RegRole::Arg5)?,
+            digit: arch.reg(RegRole::SyscallNr)?,
+        })
+    }
+}
+
+fn label(routine: &str, name: &str) -> String {
+    format!("{}_{}", routine, name)
+}
+
+fn mem(base: Register, offset: i32) -> Mem {
+    Mem { base, offset }
+}
+
+fn imm(value: i64) -> Operand {
+    Operand::Imm(value)
+}
+
+fn jump_if(program: &mut Program, cond: Cond, target: String) {
+    program.push(Instr::JumpIf { cond, target });
+}
+
+// stores one byte at the cursor and advances it
+fn put_char(program: &mut Program, r: &IntRegs, c: u8) {
+    program.push(Instr::Mov {
+        dst: r.digit,
+        src: imm(c as i64),
+    });
+    program.push(Instr::Store {
+        dst: mem(r.cur, 0),
+        src: r.digit,
+        width: Width::W8,
+    });
+    program.push(Instr::Add {
+        dst: r.cur,
+        src: imm(1),
+    });
+}
+
+// loads the power of ten the table register points at
+fn load_power(program: &mut Program, r: &IntRegs) {
+    if r.pair {
+        program.push(Instr::Load {
+            dst: r.plo,
+            src: mem(r.table, 0),
+            width: Width::W32,
+        });
+        program.push(Instr::Load {
+            dst: r.phi,
+            src: mem(r.table, 4),
+            width: Width::W32,
+        });
+    } else {
+        program.push(Instr::Load {
+            dst: r.plo,
+            src: mem(r.table, 0),
+            width: Width::W64,
+        });
+    }
+}
+
+// jumps to `target` when value < power, both unsigned
+fn jump_if_below(program: &mut Program, r: &IntRegs, target: &str, scratch: &str) {
+    if r.pair {
+        program.push(Instr::Cmp {
+            lhs: r.hi,
+            rhs: Operand::Reg(r.phi),
+        });
+        jump_if(program, Cond::Below, target.to_string());
+        jump_if(program, Cond::Above, label(FMT_I64, scratch));
+    }
+    program.push(Instr::Cmp {
+        lhs: r.lo,
+        rhs: Operand::Reg(r.plo),
+    });
+    jump_if(program, Cond::Below, target.to_string());
+    if r.pair {
+        program.push(Instr::Label(label(FMT_I64, scratch)));
+    }
+}
+
+// saves the return address before a routine calls another one
+fn enter(program: &mut Program, arch: &Target) {
+    if let Some(lr) = arch.link_reg() {
+        program.push(Instr::Push(lr));
+    }
+}
+
+fn leave(program: &mut Program, arch: &Target) {
+    if let Some(lr) = arch.link_reg() {
+        program.push(Instr::Pop(lr));
+    }
+    program.push(Instr::Ret);
+}
+
+// byte by byte, the same on every target
+fn copy(program: &mut Program, arch: &Target) -> Result<(), MakeError> {
+    let r = IntRegs::new(arch)?;
+    let src = arch.reg(RegRole::Arg1)?;
+    let l = |name: &str| label(COPY, name);
+    program.push(Instr::Label(COPY.to_string()));
+    program.push(Instr::Cmp {
+        lhs: r.table,
+        rhs: imm(0),
+    });
+    jump_if(program, Cond::Eq, l("done"));
+    program.push(Instr::Load {
+        dst: r.digit,
+        src: mem(src, 0),
+        width: Width::W8,
+    });
+    program.push(Instr::Store {
+        dst: mem(r.cur, 0),
+        src: r.digit,
+        width: Width::W8,
+    });
+    program.push(Instr::Add {
+        dst: src,
+        src: imm(1),
+    });
+    program.push(Instr::Add {
+        dst: r.cur,
+        src: imm(1),
+    });
+    program.push(Instr::Sub {
+        dst: r.table,
+        src: imm(1),
+    });
+    program.push(Instr::Jump(COPY.to_string()));
+    program.push(Instr::Label(l("done")));
+    program.push(Instr::Ret);
+    Ok(())
+}
+
+// signed decimal formatter. the magnitude is built by subtracting powers of
+// ten (10^18 down to 1) so no target needs a divide instruction, which 32-bit
+// arm lacks. i64::MIN works because the negated value is treated as unsigned
+fn fmt_int(program: &mut Program, arch: &Target) -> Result<(), MakeError> {
+    let r = IntRegs::new(arch)?;
+    let l = |name: &str| label(FMT_I64, name);
+
+    program.push(Instr::Label(FMT_I32.to_string()));
+    if r.pair {
+        program.push(Instr::Mov {
+            dst: r.hi,
+            src: imm(0),
+        });
+        program.push(Instr::Cmp {
+            lhs: r.lo,
+            rhs: imm(0),
+        });
+        jump_if(program, Cond::Ge, FMT_I64.to_string());
+        program.push(Instr::Mov {
+            dst: r.hi,
+            src: imm(-1),
+        });
+    }
+    program.push(Instr::Label(FMT_I64.to_string()));
+    program.push(Instr::Cmp {
+        lhs: if r.pair { r.hi } else { r.lo },
+        rhs: imm(0),
+    });
+    jump_if(program, Cond::Ge, l("abs"));
+    put_char(program, &r, b'-');
+    program.push(Instr::Neg(r.lo));
+    if r.pair {
+        // -(hi:lo) is (-hi - 1):-lo unless lo was zero
+        program.push(Instr::Neg(r.hi));
+        program.push(Instr::Cmp {
+            lhs: r.lo,
+            rhs: imm(0),
+        });
+        jump_if(program, Cond::Eq, l("abs"));
+        program.push(Instr::Sub {
+            dst: r.hi,
+            src: imm(1),
+        });
+    }
+    program.push(Instr::Label(l("abs")));
+    program.push(Instr::Mov {
+        dst: r.table,
+        src: Operand::Label(POW10.to_string()),
+    });
+
+    // skip leading zeros, but always keep the last power so 0 prints as "0"
+    program.push(Instr::Label(l("skip")));
+    load_power(program, &r);
+    program.push(Instr::Cmp {
+        lhs: r.plo,
+        rhs: imm(1),
+    });
+    jump_if(program, Cond::Eq, l("digits"));
+    jump_if_below(program, &r, &l("next"), "skip_lo");
+    program.push(Instr::Jump(l("digits")));
+    program.push(Instr::Label(l("next")));
+    program.push(Instr::Add {
+        dst: r.table,
+        src: imm(8),
+    });
+    program.push(Instr::Jump(l("skip")));
+
+    // one digit per power: count how many times it can be subtracted
+    program.push(Instr::Label(l("digits")));
+    load_power(program, &r);
+    program.push(Instr::Cmp {
+        lhs: r.plo,
+        rhs: imm(0),
+    });
+    jump_if(program, Cond::Eq, l("done"));
+    program.push(Instr::Mov {
+        dst: r.digit,
+        src: imm(b'0' as i64),
+    });
+    program.push(Instr::Label(l("count")));
+    jump_if_below(program, &r, &l("store"), "count_lo");
+    if r.pair {
+        program.push(Instr::Cmp {
+            lhs: r.lo,
+            rhs: Operand::Reg(r.plo),
+        });
+        jump_if(program, Cond::AboveEq, l("no_borrow"));
+        program.push(Instr::Sub {
+            dst: r.hi,
+            src: imm(1),
+        });
+        program.push(Instr::Label(l("no_borrow")));
+    }
+    program.push(Instr::Sub {
+        dst: r.lo,
+        src: Operand::Reg(r.plo),
+    });
+    if r.pair {
+        program.push(Instr::Sub {
+            dst: r.hi,
+            src: Operand::Reg(r.phi),
+        });
+    }
+    program.push(Instr::Add {
+        dst: r.digit,
+        src: imm(1),
+    });
+    program.push(Instr::Jump(l("count")));
+    program.push(Instr::Label(l("store")));
+    program.push(Instr::Store {
+        dst: mem(r.cur, 0),
+        src: r.digit,
+        width: Width::W8,
+    });
+    program.push(Instr::Add {
+        dst: r.cur,
+        src: imm(1),
+    });
+    program.push(Instr::Add {
+        dst: r.table,
+        src: imm(8),
+    });
+    program.push(Instr::Jump(l("digits")));
+    program.push(Instr::Label(l("done")));
+    program.push(Instr::Ret);
+
+    let mut powers: Vec<i64> = (0..19).rev().map(|e| 10i64.pow(e)).collect();
+    powers.push(0);
+    program.data(POW10, DataValue::Table(powers));
+    Ok(())
+}
+
+// offsets into FCONST
+const F_ZERO: i32 = 0;
+const F_HALF: i32 = 8;
+const F_TEN: i32 = 16;
+const F_TWO_63: i32 = 24;
+const F_MAX: i32 = 32;
+const F_TWO_32: i32 = 40;
+const F_TWO_M32: i32 = 48;
+const F_TWO_31: i32 = 56;
+
+// the frame of fmt_f64: 10^precision, the scale exponent (later the position
+// of the point) and the scaled fraction
+const SLOT_POW: i32 = 0;
+const SLOT_EXP: i32 = 8;
+const SLOT_FRAC: i32 = 16;
+
+// fixed point formatter: [-]int.frac rounded to the precision, "nan" and
+// "inf" for the special values. values of 2^63 and up are divided by ten
+// until they fit and printed with trailing zeros, so only their leading
+// digits are exact. the sign is only printed below zero, so -0.0 prints as
+// 0.000000, and halves round away from zero
+fn fmt_float(program: &mut Program, arch: &Target) -> Result<(), MakeError> {
+    let r = IntRegs::new(arch)?;
+    let word = arch.word();
+    let sp = arch.reg(RegRole::StackPointer)?;
+    let x = arch.reg(RegRole::FloatScratch)?;
+    let t = arch.reg(RegRole::FloatTemp1)?;
+    let k = arch.reg(RegRole::FloatTemp2)?;
+    let l = |name: &str| label(FMT_F64, name);
+    let fconst = |program: &mut Program, dst: Register, offset: i32| {
+        program.push(Instr::Mov {
+            dst: r.table,
+            src: Operand::Label(FCONST.to_string()),
+        });
+        program.push(Instr::Load {
+            dst,
+            src: mem(r.table, offset),
+            width: Width::W64,
+        });
+    };
+    let slot = |offset: i32| mem(sp, offset);
+
+    program.push(Instr::Label(FMT_F64.to_string()));
+    enter(program, arch);
+    program.push(Instr::Sub {
+        dst: sp,
+        src: imm(32),
+    });
+    program.push(Instr::Store {
+        dst: slot(SLOT_POW),
+        src: r.table,
+        width: word,
+    });
+
+    program.push(Instr::FCmp { lhs: x, rhs: x });
+    jump_if(program, Cond::Unordered, l("nan"));
+    fconst(program, t, F_ZERO);
+    program.push(Instr::FCmp { lhs: x, rhs: t });
+    jump_if(program, Cond::AboveEq, l("abs"));
+    put_char(program, &r, b'-');
+    program.push(Instr::FSub { dst: t, src: x });
+    program.push(Instr::FMov { dst: x, src: t });
+    program.push(Instr::Label(l("abs")));
+    fconst(program, t, F_MAX);
+    program.push(Instr::FCmp { lhs: x, rhs: t });
+    jump_if(program, Cond::Above, l("inf"));
+
+    // bring the value under 2^63, counting the divisions
+    program.push(Instr::Mov {
+        dst: r.lo,
+        src: imm(0),
+    });
+    fconst(program, k, F_TEN);
+    program.push(Instr::Label(l("scale")));
+    fconst(program, t, F_TWO_63);
+    program.push(Instr::FCmp { lhs: x, rhs: t });
+    jump_if(program, Cond::Below, l("split"));
+    program.push(Instr::FDiv { dst: x, src: k });
+    program.push(Instr::Add {
+        dst: r.lo,
+        src: imm(1),
+    });
+    program.push(Instr::Jump(l("scale")));
+    program.push(Instr::Label(l("split")));
+    program.push(Instr::Store {
+        dst: slot(SLOT_EXP),
+        src: r.lo,
+        width: word,
+    });
+
+    // integer part into lo (and hi), leaving the fraction in x
+    if r.pair {
+        // hi = x / 2^32, then the remainder below 2^32 as an unsigned lo. the
+        // conversions are signed, so remainders from 2^31 are biased first
+        program.push(Instr::FMov { dst: t, src: x });
+        fconst(program, k, F_TWO_M32);
+        program.push(Instr::FMul { dst: t, src: k });
+        program.push(Instr::FToInt { dst: r.hi, src: t });
+        program.push(Instr::IntToF { dst: t, src: r.hi });
+        fconst(program, k, F_TWO_32);
+        program.push(Instr::FMul { dst: t, src: k });
+        program.push(Instr::FSub { dst: x, src: t });
+        fconst(program, k, F_TWO_31);
+        program.push(Instr::FCmp { lhs: x, rhs: k });
+        jump_if(program, Cond::Below, l("low"));
+        program.push(Instr::FSub { dst: x, src: k });
+        program.push(Instr::FToInt { dst: r.lo, src: x });
+        program.push(Instr::IntToF { dst: t, src: r.lo });
+        program.push(Instr::FSub { dst: x, src: t });
+        program.push(Instr::Add {
+            dst: r.lo,
+            src: imm(i32::MIN as i64),
+        });
+        program.push(Instr::Jump(l("fraction")));
+        program.push(Instr::Label(l("low")));
+    }
+    program.push(Instr::FToInt { dst: r.lo, src: x });
+    program.push(Instr::IntToF { dst: t, src: r.lo });
+    program.push(Instr::FSub { dst: x, src: t });
+
+    // frac = trunc(fraction * 10^precision + 0.5), carrying into the integer
+    // part when it rounds up to 10^precision
+    program.push(Instr::Label(l("fraction")));
+    program.push(Instr::Load {
+        dst: r.plo,
+        src: slot(SLOT_POW),
+        width: word,
+    });
+    program.push(Instr::IntToF { dst: t, src: r.plo });
+    program.push(Instr::FMul { dst: x, src: t });
+    fconst(program, t, F_HALF);
+    program.push(Instr::FAdd { dst: x, src: t });
+    program.push(Instr::FToInt {
+        dst: r.digit,
+        src: x,
+    });
+    program.push(Instr::Cmp {
+        lhs: r.digit,
+        rhs: Operand::Reg(r.plo),
+    });
+    jump_if(program, Cond::Below, l("int"));
+    program.push(Instr::Sub {
+        dst: r.digit,
+        src: Operand::Reg(r.plo),
+    });
+    program.push(Instr::Add {
+        dst: r.lo,
+        src: imm(1),
+    });
+    if r.pair {
+        program.push(Instr::Cmp {
+            lhs: r.lo,
+            rhs: imm(0),
+        });
+        jump_if(program, Cond::Ne, l("int"));
+        program.push(Instr::Add {
+            dst: r.hi,
+            src: imm(1),
+        });
+    }
+    program.push(Instr::Label(l("int")));
+    program.push(Instr::Store {
+        dst: slot(SLOT_FRAC),
+        src: r.digit,
+        width: word,
+    });
+    program.push(Instr::Call(FMT_I64.to_string()));
+
+    // the digits the scaling divided away
+    program.push(Instr::Load {
+        dst: r.lo,
+        src: slot(SLOT_EXP),
+        width: word,
+    });
+    program.push(Instr::Label(l("zeros")));
+    program.push(Instr::Cmp {
+        lhs: r.lo,
+        rhs: imm(0),
+    });
+    jump_if(program, Cond::Eq, l("point"));
+    put_char(program, &r, b'0');
+    program.push(Instr::Sub {
+        dst: r.lo,
+        src: imm(1),
+    });
+    program.push(Instr::Jump(l("zeros")));
+
+    // frac + 10^precision prints as "1" followed by exactly `precision`
+    // digits; the "1" is then overwritten with the point
+    program.push(Instr::Label(l("point")));
+    program.push(Instr::Load {
+        dst: r.plo,
+        src: slot(SLOT_POW),
+        width: word,
+    });
+    program.push(Instr::Cmp {
+        lhs: r.plo,
+        rhs: imm(1),
+    });
+    jump_if(program, Cond::Eq, l("done"));
+    program.push(Instr::Store {
+        dst: slot(SLOT_EXP),
+        src: r.cur,
+        width: word,
+    });
+    program.push(Instr::Load {
+        dst: r.lo,
+        src: slot(SLOT_FRAC),
+        width: word,
+    });
+    program.push(Instr::Add {
+        dst: r.lo,
+        src: Operand::Reg(r.plo),
+    });
+    if r.pair {
+        program.push(Instr::Mov {
+            dst: r.hi,
+            src: imm(0),
+        });
+    }
+    program.push(Instr::Call(FMT_I64.to_string()));
+    program.push(Instr::Load {
+        dst: r.table,
+        src: slot(SLOT_EXP),
+        width: word,
+    });
+    program.push(Instr::Mov {
+        dst: r.digit,
+        src: imm(b'.' as i64),
+    });
+    program.push(Instr::Store {
+        dst: mem(r.table, 0),
+        src: r.digit,
+        width: Width::W8,
+    });
+    program.push(Instr::Jump(l("done")));
+
+    program.push(Instr::Label(l("nan")));
+    for c in b"nan" {
+        put_char(program, &r, *c);
+    }
+    program.push(Instr::Jump(l("done")));
+    program.push(Instr::Label(l("inf")));
+    for c in b"inf" {
+        put_char(program, &r, *c);
+    }
+    program.push(Instr::Label(l("done")));
+    program.push(Instr::Add {
+        dst: sp,
+        src: imm(32),
+    });
+    leave(program, arch);
+
+    let consts = [
+        0.0,
+        0.5,
+        10.0,
+        9223372036854775808.0,
+        f64::MAX,
+        4294967296.0,
+        1.0 / 4294967296.0,
+        2147483648.0,
+    ];
+    program.data(
+        FCONST,
+        DataValue::Table(consts.iter().map(|c| f64::to_bits(*c) as i64).collect()),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        arch::{Arch, Bit, OS},
+        coreasm::CoreAsm,
+        maker,
+    };
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn floats_print_in_fixed_point() {
+        use std::{fs, process::Command};
+        let asm = CoreAsm::builder()
+            .f64("nan", f64::NAN)
+            .f64("inf", f64::INFINITY)
+            .f64("ninf", f64::NEG_INFINITY)
+            .f64("half", 2.5)
+            .f64("small", 0.4)
+            .f64("almost", 0.9999999)
+            .f64("eighth", -0.125)
+            .f64("third", 1.0 / 3.0)
+            .f64("zero", -0.0)
+            .f32("single", 1.5)
+            .print(|p| p.var("nan").text(" ").var("inf").text(" ").var("ninf").nl())
+            .print(|p| p.float("half", 0).text(" ").float("small", 0).nl())
+            .print(|p| p.var("almost").text(" ").float("almost", 2).nl())
+            .print(|p| p.var("eighth").text(" ").float("eighth", 2).nl())
+            .print(|p| p.float("third", MAX_PRECISION).nl())
+            .print(|p| p.var("zero").text(" ").var("single").nl())
+            .exit(0)
+            .build()
+            .unwrap();
+        let target = Target::new(Bit::X64, Arch::X86, OS::Linux);
+        let image = maker::executable(&asm, target).unwrap();
+        let path = std::env::temp_dir().join(format!("coreasm-float-{}", std::process::id()));
+        crate::elf::write(&path, &image).unwrap();
+        let output = Command::new(&path).output().unwrap();
+        fs::remove_file(&path).unwrap();
+        // halves round away from zero, and zero prints without its sign
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "nan inf -inf\n\
+             3 0\n\
+             1.000000 1.00\n\
+             -0.125000 -0.13\n\
+             0.333333333\n\
+             0.000000 1.500000\n"
+        );
+    }
+
+    #[test]
+    fn precision_is_capped() {
+        let built = CoreAsm::builder()
+            .f64("x", 1.0)
+            .print(|p| p.float("x", MAX_PRECISION + 1))
+            .build();
+        assert!(matches!(
+            built,
+            Err(MakeError::BadPrecision { precision, .. }) if precision == MAX_PRECISION + 1
+        ));
+    }
+}